@@ -0,0 +1,463 @@
+// Runs a scene file without a browser and writes what happened, for offline experiments.
+//
+//     runner level.json --steps 600 --format json --output states.jsonl --frames frames/
+use impulse_engine_wasm::level::*;
+use impulse_engine_wasm::math::*;
+use impulse_engine_wasm::rube::*;
+use impulse_engine_wasm::scene::*;
+use impulse_engine_wasm::svg::*;
+use serde::Serialize;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: runner <scene> [options]
+
+The scene is a level (.json or .ron), an SVG drawing (.svg) or, with --rube, a R.U.B.E. scene.
+
+options:
+    --steps N           number of steps to run, 600 by default
+    --dt SECONDS        timestep, the scene's own by default
+    --iterations N      velocity iterations per step, the scene's own by default
+    --format csv|json   per-step body states as CSV rows or JSON lines, csv by default
+    --output FILE       where to write the states, standard output by default
+    --no-states         do not write the states, only the timing
+    --frames DIR        save numbered PNG frames in DIR
+    --frame-size WxH    size of the frames in pixels, 800x800 by default
+    --every N           save a frame every N steps, 1 by default
+    --rube              read the scene as a R.U.B.E. JSON scene";
+
+#[derive(PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Options {
+    scene: PathBuf,
+    steps: u32,
+    dt: Option<f64>,
+    iterations: Option<u32>,
+    format: Format,
+    output: Option<PathBuf>,
+    states: bool,
+    frames: Option<PathBuf>,
+    frame_size: (u32, u32),
+    every: u32,
+    rube: bool,
+}
+
+fn parse<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value \"{}\" for {}", value, option))
+}
+
+impl Options {
+    // None when the usage is asked for
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Self>, String> {
+        let mut scene = None;
+        let mut options = Options {
+            scene: PathBuf::new(),
+            steps: 600,
+            dt: None,
+            iterations: None,
+            format: Format::Csv,
+            output: None,
+            states: true,
+            frames: None,
+            frame_size: (800, 800),
+            every: 1,
+            rube: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--steps" => options.steps = parse(&arg, args.next())?,
+                "--dt" => options.dt = Some(parse(&arg, args.next())?),
+                "--iterations" => options.iterations = Some(parse(&arg, args.next())?),
+                "--format" => {
+                    options.format = match parse::<String>(&arg, args.next())?.as_str() {
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        other => return Err(format!("unknown format \"{}\"", other)),
+                    }
+                }
+                "--output" => options.output = Some(parse(&arg, args.next())?),
+                "--no-states" => options.states = false,
+                "--frames" => options.frames = Some(parse(&arg, args.next())?),
+                "--frame-size" => {
+                    let size: String = parse(&arg, args.next())?;
+                    let mut parts = size.split('x').map(u32::from_str);
+                    options.frame_size = match (parts.next(), parts.next(), parts.next()) {
+                        (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 => (w, h),
+                        _ => return Err(format!("invalid frame size \"{}\"", size)),
+                    };
+                }
+                "--every" => options.every = parse(&arg, args.next())?,
+                "--rube" => options.rube = true,
+                "-h" | "--help" => return Ok(None),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if scene.is_none() => scene = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument \"{}\"", arg)),
+            }
+        }
+        options.scene = scene.ok_or_else(|| "no scene file given".to_string())?;
+        if options.dt.is_some_and(|dt| !dt.is_finite() || dt <= 0.0) {
+            return Err("the timestep must be positive and finite".to_string());
+        }
+        if options.every == 0 {
+            return Err("--every must be at least 1".to_string());
+        }
+        Ok(Some(options))
+    }
+}
+
+// Imported scenes report what they could not convert
+fn load(options: &Options) -> Result<Scene, LevelError> {
+    let path = &options.scene;
+    let (level, warnings) = if options.rube {
+        let import = RubeImport::load(path)?;
+        (import.level, import.warnings)
+    } else if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("svg"))
+    {
+        let import = SvgImport::load(path, &SvgOptions::default())?;
+        (import.level, import.warnings)
+    } else {
+        (Level::load(path)?, Vec::new())
+    };
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+    Scene::from_level(&level)
+}
+
+#[derive(Serialize)]
+struct BodyState {
+    x: f64,
+    y: f64,
+    angle: f64,
+    vx: f64,
+    vy: f64,
+    angular_velocity: f64,
+}
+
+#[derive(Serialize)]
+struct StepState {
+    step: u32,
+    time: f64,
+    bodies: Vec<BodyState>,
+}
+
+fn body_states(scene: &Scene) -> Vec<BodyState> {
+    scene
+        .bodies()
+        .iter()
+        .map(|body| {
+            let object = body.object();
+            let object = object.borrow();
+            BodyState {
+                x: object.position.x.to_f64(),
+                y: object.position.y.to_f64(),
+                angle: object.orient.to_f64(),
+                vx: object.velocity.x.to_f64(),
+                vy: object.velocity.y.to_f64(),
+                angular_velocity: object.angular_velocity.to_f64(),
+            }
+        })
+        .collect()
+}
+
+fn write_states(out: &mut dyn Write, format: &Format, state: &StepState) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            for (i, body) in state.bodies.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{},{}",
+                    state.step,
+                    state.time,
+                    i,
+                    body.x,
+                    body.y,
+                    body.angle,
+                    body.vx,
+                    body.vy,
+                    body.angular_velocity
+                )?;
+            }
+            Ok(())
+        }
+        Format::Json => {
+            serde_json::to_writer(&mut *out, state)?;
+            writeln!(out)
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// Only `Scene::step` is timed, writing states and frames is not
+fn print_timing(times: &mut [Duration], bodies: usize) {
+    if times.is_empty() {
+        return;
+    }
+    times.sort();
+    let total: Duration = times.iter().sum();
+    let percentile = |p: f64| times[((times.len() - 1) as f64 * p).round() as usize];
+    eprintln!("steps:  {} with {} bodies", times.len(), bodies);
+    eprintln!(
+        "total:  {:.3} ms, {:.1} steps/s",
+        millis(total),
+        times.len() as f64 / total.as_secs_f64().max(1e-9)
+    );
+    eprintln!(
+        "step:   mean {:.3} ms, min {:.3} ms, median {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        millis(total) / times.len() as f64,
+        millis(times[0]),
+        millis(percentile(0.5)),
+        millis(percentile(0.99)),
+        millis(times[times.len() - 1])
+    );
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut scene = load(options).map_err(|e| format!("{}: {}", options.scene.display(), e))?;
+    if let Some(dt) = options.dt {
+        scene.set_timestep(real(dt));
+    }
+    if let Some(iterations) = options.iterations {
+        scene.set_iterations(iterations);
+    }
+    let dt = scene.timestep().to_f64();
+
+    let mut out: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let write_error = |e: io::Error| format!("cannot write the states: {}", e);
+    if options.states && options.format == Format::Csv {
+        writeln!(out, "step,time,body,x,y,angle,vx,vy,angular_velocity").map_err(write_error)?;
+    }
+
+    let mut times = Vec::with_capacity(options.steps as usize);
+    let mut advance = |scene: &mut Scene, step: u32| -> io::Result<()> {
+        let start = Instant::now();
+        scene.step();
+        times.push(start.elapsed());
+
+        if options.states {
+            let state = StepState {
+                step,
+                time: f64::from(step) * dt,
+                bodies: body_states(scene),
+            };
+            write_states(&mut *out, &options.format, &state)
+                .map_err(|e| io::Error::new(e.kind(), write_error(e)))?;
+        }
+        Ok(())
+    };
+    match options.frames {
+        Some(ref directory) => scene
+            .record_png_sequence(
+                options.steps,
+                options.every,
+                options.frame_size.0,
+                options.frame_size.1,
+                directory,
+                advance,
+            )
+            .map_err(|e| e.to_string())?,
+        None => {
+            for step in 1..=options.steps {
+                advance(&mut scene, step).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    out.flush().map_err(write_error)?;
+    print_timing(&mut times, scene.bodies().len());
+    Ok(())
+}
+
+fn main() {
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n", message);
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&options) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn from_args(args: &str) -> Result<Option<Options>, String> {
+        Options::from_args(args.split_whitespace().map(String::from))
+    }
+
+    fn options(args: &str) -> Options {
+        from_args(args).unwrap().unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let options = options("level.json");
+        assert_eq!(options.scene, PathBuf::from("level.json"));
+        assert_eq!(options.steps, 600);
+        assert_eq!(options.dt, None);
+        assert_eq!(options.iterations, None);
+        assert!(options.format == Format::Csv);
+        assert_eq!(options.output, None);
+        assert!(options.states);
+        assert_eq!(options.frames, None);
+        assert_eq!(options.frame_size, (800, 800));
+        assert_eq!(options.every, 1);
+        assert!(!options.rube);
+    }
+
+    #[test]
+    fn every_option() {
+        let options = options(
+            "--steps 10 --dt 0.01 --iterations 4 --format json --output out.jsonl --no-states \
+             --frames frames --frame-size 320x240 --every 5 --rube scene.json",
+        );
+        assert_eq!(options.scene, PathBuf::from("scene.json"));
+        assert_eq!(options.steps, 10);
+        assert_eq!(options.dt, Some(0.01));
+        assert_eq!(options.iterations, Some(4));
+        assert!(options.format == Format::Json);
+        assert_eq!(options.output, Some(PathBuf::from("out.jsonl")));
+        assert!(!options.states);
+        assert_eq!(options.frames, Some(PathBuf::from("frames")));
+        assert_eq!(options.frame_size, (320, 240));
+        assert_eq!(options.every, 5);
+        assert!(options.rube);
+    }
+
+    #[test]
+    fn help_is_not_an_error() {
+        for args in &[
+            "-h",
+            "--help",
+            "level.json --steps 10 --help",
+            "--help --bogus",
+        ] {
+            assert!(matches!(from_args(args), Ok(None)), "{}", args);
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        for args in &[
+            "",
+            "--steps 10",
+            "level.json --steps",
+            "level.json --steps ten",
+            "level.json --format xml",
+            "level.json --frame-size 0x10",
+            "level.json --frame-size 10x10x10",
+            "level.json --frame-size 10",
+            "level.json --dt -1",
+            "level.json --dt NaN",
+            "level.json --dt inf",
+            "level.json --every 0",
+            "level.json --bogus",
+            "level.json other.json",
+        ] {
+            assert!(from_args(args).is_err(), "{}", args);
+        }
+    }
+
+    // A falling circle over a floor, in a directory of its own
+    fn level_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("runner-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let level = r#"{ "version": 1, "bodies": [
+            { "shape": { "Circle": { "radius": 1 } }, "position": { "x": 5, "y": 5 } },
+            { "shape": { "Box": { "half_width": 9, "half_height": 0.5 } },
+              "position": { "x": 10, "y": 17 }, "is_static": true }
+        ] }"#;
+        fs::write(directory.join("level.json"), level).unwrap();
+        directory
+    }
+
+    #[test]
+    fn runs_a_level_to_csv_and_frames() {
+        let directory = level_directory("csv");
+        let options = options(&format!(
+            "{} --steps 7 --output {} --frames {} --every 3 --frame-size 40x40",
+            directory.join("level.json").display(),
+            directory.join("states.csv").display(),
+            directory.join("frames").display(),
+        ));
+        run(&options).unwrap();
+
+        let csv = fs::read_to_string(directory.join("states.csv")).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "step,time,body,x,y,angle,vx,vy,angular_velocity");
+        // One row per body and step
+        assert_eq!(lines.len(), 1 + 7 * 2);
+        assert!(lines[1].starts_with("1,"));
+        assert!(lines[14].starts_with("7,"));
+        for line in &lines[1..] {
+            assert_eq!(line.split(',').count(), 9, "{}", line);
+        }
+
+        let mut frames: Vec<_> = fs::read_dir(directory.join("frames"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        frames.sort();
+        assert_eq!(frames, ["frame_00000.png", "frame_00001.png"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn runs_a_level_to_json_lines() {
+        let directory = level_directory("json");
+        let options = options(&format!(
+            "{} --steps 5 --format json --output {}",
+            directory.join("level.json").display(),
+            directory.join("states.jsonl").display(),
+        ));
+        run(&options).unwrap();
+
+        let json = fs::read_to_string(directory.join("states.jsonl")).unwrap();
+        let lines: Vec<_> = json.lines().collect();
+        assert_eq!(lines.len(), 5);
+        for (i, line) in lines.iter().enumerate() {
+            let state: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(state["step"], i + 1);
+            let bodies = state["bodies"].as_array().unwrap();
+            assert_eq!(bodies.len(), 2);
+            // The circle falls and the floor stays
+            assert!(bodies[0]["vy"].as_f64().unwrap() > 0.0);
+            assert_eq!(bodies[1]["y"], 17.0);
+        }
+        assert!(!directory.join("frames").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
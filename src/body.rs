@@ -1,24 +1,28 @@
+use crate::geometry::*;
+use crate::material::*;
 use crate::math::*;
 use crate::rand::*;
-use crate::scene::*;
+use crate::render::*;
 use std::cell::RefCell;
-use std::f64::consts::PI;
+use std::error::Error;
+use std::fmt;
 use std::rc::Rc;
 
 use downcast_rs::{impl_downcast, Downcast};
+use serde::{Deserialize, Serialize};
 
-const GRAVITY: Vector2d<f64> = Vector2d { x: 0.0, y: 9.8 };
-
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
 impl Color {
-    fn random() -> Self {
-        let mut rng = Rng::new();
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+    pub fn random(rng: &mut Rng) -> Self {
         Self {
             r: rng.gen_range(0, 200) as u8,
             g: rng.gen_range(0, 200) as u8,
@@ -27,121 +31,339 @@ impl Color {
     }
 }
 
+// Which bodies may collide, bodies in the same non-zero group always collide if the group is
+// positive and never if it is negative, otherwise each mask has to accept the other category
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    pub category_bits: u16,
+    pub mask_bits: u16,
+    pub group_index: i16,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            category_bits: 0x0001,
+            mask_bits: 0xFFFF,
+            group_index: 0,
+        }
+    }
+}
+
+impl Filter {
+    pub fn should_collide(&self, other: &Filter) -> bool {
+        if self.group_index == other.group_index && self.group_index != 0 {
+            return self.group_index > 0;
+        }
+        (self.mask_bits & other.category_bits) != 0 && (other.mask_bits & self.category_bits) != 0
+    }
+}
+
+// Mass properties given instead of computed from the shape, `center` is in local space and
+// `inertia` is about it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MassData {
+    pub mass: Real,
+    pub center: Vector2d<Real>,
+    pub inertia: Real,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MassDataError {
+    InvalidMass,
+    InvalidCenter,
+    InvalidInertia,
+}
+
+impl fmt::Display for MassDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            MassDataError::InvalidMass => "mass must be positive and finite",
+            MassDataError::InvalidCenter => "center of mass must be finite",
+            MassDataError::InvalidInertia => "inertia must be positive and finite",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for MassDataError {}
+
+// The part of an object that stepping changes
+#[derive(Clone, Copy)]
+pub struct ObjectState {
+    pub position: Vector2d<Real>,
+    pub velocity: Vector2d<Real>,
+    pub force: Vector2d<Real>,
+    pub angular_velocity: Real,
+    pub torque: Real,
+    pub orient: Real,
+}
+
 #[derive(Clone)]
 pub struct Object {
-    pub position: Vector2d<f64>,
-    pub velocity: Vector2d<f64>,
-    pub force: Vector2d<f64>,
+    pub position: Vector2d<Real>,
+    pub velocity: Vector2d<Real>,
+    pub force: Vector2d<Real>,
 
-    pub density: f64,
-    pub inertia: f64,
-    pub inverse_inertia: f64,
-    pub mass: f64,
-    pub inverse_mass: f64,
+    pub density: Real,
+    // Center of mass in local space, the body turns around it
+    pub center: Vector2d<Real>,
+    pub inertia: Real,
+    pub inverse_inertia: Real,
+    pub mass: Real,
+    pub inverse_mass: Real,
+    // Set by `set_mass_data`, None while the mass comes from the shape
+    pub mass_data: Option<MassData>,
 
-    pub angular_velocity: f64,
-    pub torque: f64,
-    pub orient: f64,
+    pub angular_velocity: Real,
+    pub torque: Real,
+    pub orient: Real,
+    // Fixed rotation bodies keep their inertia but never turn
+    pub fixed_rotation: bool,
 
-    pub static_friction: f64,
-    pub dynamic_friction: f64,
-    pub restitution: f64,
+    pub material: Material,
+    // Speed of the surface along itself, like a conveyor belt. Positive speeds move what rests on
+    // top to the right.
+    pub tangent_speed: Real,
+    // Friction scale when sliding along the local x and y axes, (1, 1) is the same everywhere
+    pub anisotropic_friction: Vector2d<Real>,
+
+    pub filter: Filter,
+    // Sensors report overlaps but never push other bodies
+    pub is_sensor: bool,
+    // One-way platforms only stop bodies landing on them from above, their up is local -y
+    pub one_way: bool,
 
     pub color: Color,
 }
 
 impl Object {
-    fn new(x: f64, y: f64) -> Self {
+    fn new(x: Real, y: Real) -> Self {
         Self {
             position: Vector2d::new(x, y),
             velocity: Vector2d::zero(),
             force: Vector2d::zero(),
-            density: 1.0,
-            inertia: 1.0,
-            inverse_inertia: 1.0,
-            mass: 1.0,
-            inverse_mass: 1.0,
-            angular_velocity: 0.0,
-            torque: 0.0,
-            orient: 0.0,
-            static_friction: 0.5,
-            dynamic_friction: 0.3,
-            restitution: 0.2,
-            color: Color::random(),
-        }
-    }
-
-    pub fn apply_impulse(&mut self, impulse: Vector2d<f64>, contact_vector: Vector2d<f64>) {
+            density: real(1.0),
+            center: Vector2d::zero(),
+            inertia: real(1.0),
+            inverse_inertia: real(1.0),
+            mass: real(1.0),
+            inverse_mass: real(1.0),
+            mass_data: None,
+            angular_velocity: real(0.0),
+            torque: real(0.0),
+            orient: real(0.0),
+            fixed_rotation: false,
+            material: Material::default(),
+            tangent_speed: real(0.0),
+            anisotropic_friction: Vector2d::new(real(1.0), real(1.0)),
+            filter: Filter::default(),
+            is_sensor: false,
+            one_way: false,
+            color: Color::new(0, 0, 0),
+        }
+    }
+
+    pub fn apply_impulse(&mut self, impulse: Vector2d<Real>, contact_vector: Vector2d<Real>) {
         self.velocity += impulse * self.inverse_mass;
         self.angular_velocity += contact_vector.cross_product(impulse) * self.inverse_inertia;
         // console!(log, "angular_velocity: %f", self.angular_velocity);
     }
 
-    fn set_static(&mut self) {
+    // Move and turn the body as if `impulse` was applied at `contact_vector` for a unit of time,
+    // used to push overlapping bodies apart
+    pub fn apply_position_impulse(
+        &mut self,
+        impulse: Vector2d<Real>,
+        contact_vector: Vector2d<Real>,
+    ) {
+        let center = self.world_center() + impulse * self.inverse_mass;
+        self.orient += contact_vector.cross_product(impulse) * self.inverse_inertia;
+        self.position = center - self.center.rotate(self.orient);
+    }
+
+    // Center of mass in world space
+    pub fn world_center(&self) -> Vector2d<Real> {
+        self.position + self.center.rotate(self.orient)
+    }
+
+    // Move the center of mass in local space while the shape stays where it is. The velocity is
+    // the one of the center of mass, which changes with it when the body turns.
+    fn move_center(&mut self, center: Vector2d<Real>) {
+        let shift = (center - self.center).rotate(self.orient);
+        self.velocity += Vector2d::new(-shift.y, shift.x) * self.angular_velocity;
+        self.center = center;
+    }
+
+    // Mass properties computed from the shape, static bodies stay static. The center of mass
+    // goes back to the body's position.
+    fn set_mass(&mut self, mass: Real, inertia: Real) {
+        self.move_center(Vector2d::zero());
+        self.mass_data = None;
+        if self.inverse_mass == real(0.0) {
+            return;
+        }
+        self.mass = mass;
+        self.inverse_mass = real(1.0) / mass;
+        self.inertia = inertia;
+        self.inverse_inertia = if self.fixed_rotation {
+            real(0.0)
+        } else {
+            real(1.0) / inertia
+        };
+    }
+
+    // Override the mass properties of the shape, `center` is the center of mass in local space
+    // and `inertia` is about that center. Nothing changes if they are invalid.
+    pub fn set_mass_data(
+        &mut self,
+        mass: Real,
+        center: Vector2d<Real>,
+        inertia: Real,
+    ) -> Result<(), MassDataError> {
+        if mass <= real(0.0) || !mass.is_finite() {
+            return Err(MassDataError::InvalidMass);
+        }
+        if inertia <= real(0.0) || !inertia.is_finite() {
+            return Err(MassDataError::InvalidInertia);
+        }
+        if !center.x.is_finite() || !center.y.is_finite() {
+            return Err(MassDataError::InvalidCenter);
+        }
+        // Like Box2D, the shape keeps its place and the center of mass moves
+        self.move_center(center);
+        self.mass_data = Some(MassData {
+            mass,
+            center,
+            inertia,
+        });
+        self.mass = mass;
+        self.inverse_mass = real(1.0) / mass;
+        self.inertia = inertia;
+        self.inverse_inertia = if self.fixed_rotation {
+            real(0.0)
+        } else {
+            real(1.0) / inertia
+        };
+        Ok(())
+    }
+
+    pub fn set_fixed_rotation(&mut self, fixed_rotation: bool) {
+        self.fixed_rotation = fixed_rotation;
+        self.angular_velocity = real(0.0);
+        self.inverse_inertia = if fixed_rotation || self.inertia.is_infinite() {
+            real(0.0)
+        } else {
+            real(1.0) / self.inertia
+        };
+    }
+
+    pub fn state(&self) -> ObjectState {
+        ObjectState {
+            position: self.position,
+            velocity: self.velocity,
+            force: self.force,
+            angular_velocity: self.angular_velocity,
+            torque: self.torque,
+            orient: self.orient,
+        }
+    }
+    pub fn set_state(&mut self, state: &ObjectState) {
+        self.position = state.position;
+        self.velocity = state.velocity;
+        self.force = state.force;
+        self.angular_velocity = state.angular_velocity;
+        self.torque = state.torque;
+        self.orient = state.orient;
+    }
+
+    // How much of the friction applies when sliding along the unit `direction` in world space
+    pub fn friction_scale(&self, direction: Vector2d<Real>) -> Real {
+        let local = direction.rotate(-self.orient);
+        Vector2d::new(
+            local.x * self.anisotropic_friction.x,
+            local.y * self.anisotropic_friction.y,
+        )
+        .len()
+    }
+
+    pub fn set_static(&mut self) {
         self.inertia = INFINITY;
-        self.inverse_inertia = 0.0;
+        self.inverse_inertia = real(0.0);
         self.mass = INFINITY;
-        self.inverse_mass = 0.0;
+        self.inverse_mass = real(0.0);
     }
 
-    fn integrate_forces(&mut self, dt: f64) {
-        if self.inverse_mass != 0.0 {
-            self.velocity += (self.force * self.inverse_mass + GRAVITY) * dt;
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        if self.inverse_mass != real(0.0) {
+            self.velocity += (self.force * self.inverse_mass + gravity) * dt;
             self.angular_velocity += self.torque * self.inverse_inertia * dt;
         }
     }
 
-    fn integrate_velocity(&mut self, dt: f64) {
-        if self.inverse_mass != 0.0 {
-            self.position += self.velocity * dt;
+    fn integrate_velocity(&mut self, dt: Real) {
+        if self.inverse_mass != real(0.0) {
+            // The velocity is the one of the center of mass
+            let center = self.world_center() + self.velocity * dt;
             self.orient += self.angular_velocity * dt;
+            self.position = center - self.center.rotate(self.orient);
         }
     }
 }
 
 pub trait RigidBody: Downcast {
-    fn draw(&self, canvas: &mut Canvas);
+    fn draw(&self, frame: &mut Frame);
 
-    fn integrate_forces(&mut self, dt: f64);
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real);
 
-    fn integrate_velocity(&mut self, dt: f64);
+    fn integrate_velocity(&mut self, dt: Real);
 
     fn object(&self) -> Rc<RefCell<Object>>;
 
-    fn radius(&self) -> f64;
+    fn radius(&self) -> Real;
+
+    // Recompute mass and inertia from the shape
+    fn set_density(&mut self, density: Real);
 }
 impl_downcast!(RigidBody);
 
 pub struct Circle {
-    pub radius: f64,
+    pub radius: Real,
     pub object: Rc<RefCell<Object>>,
 }
 
 impl RigidBody for Circle {
-    fn radius(&self) -> f64 {
+    fn radius(&self) -> Real {
         return self.radius;
     }
 
+    fn set_density(&mut self, density: Real) {
+        self.object.borrow_mut().density = density;
+        self.initialize();
+    }
+
     fn object(&self) -> Rc<RefCell<Object>> {
         self.object.clone()
     }
 
-    fn integrate_forces(&mut self, dt: f64) {
-        self.object.borrow_mut().integrate_forces(dt);
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
     }
 
-    fn integrate_velocity(&mut self, dt: f64) {
+    fn integrate_velocity(&mut self, dt: Real) {
         self.object.borrow_mut().integrate_velocity(dt);
     }
 
-    fn draw(&self, canvas: &mut Canvas) {
+    fn draw(&self, frame: &mut Frame) {
         let object = self.object.borrow();
         // Handle scale
-        let mut position = object.position;
-        position.x *= canvas.scaled_width;
-        position.y *= canvas.scaled_height;
+        let mut position = object.position.to_f64();
+        position.x *= frame.scaled_width;
+        position.y *= frame.scaled_height;
         // console!(log, "draw a circle at %f, %f", position.x,position.y);
-        let radius = self.radius * canvas.scaled_width;
+        let radius = self.radius.to_f64() * frame.scaled_width;
 
         let k_segments = 30;
         let mut theta = 0.0;
@@ -149,29 +371,29 @@ impl RigidBody for Circle {
         let mut begin = Vector2d::new(theta.cos(), theta.sin());
         begin = begin * radius;
         begin += position;
-        canvas.context.begin_path();
+        frame.context.begin_path();
         let color = format!(
             "#{:02x}{:02x}{:02x}",
             object.color.r, object.color.g, object.color.b
         );
-        canvas.context.set_stroke_style_color(&color);
-        canvas.context.move_to(begin.x, begin.y);
+        frame.context.set_stroke_style_color(&color);
+        frame.context.move_to(begin.x, begin.y);
         for _ in 0..k_segments {
             theta += inc;
             let mut point = Vector2d::new(theta.cos(), theta.sin());
             point = point * radius;
             point += position;
-            canvas.context.line_to(point.x, point.y);
+            frame.context.line_to(point.x, point.y);
         }
-        let r = Vector2d::new(0.0, radius).rotate(object.orient) + position;
-        canvas.context.move_to(position.x, position.y);
-        canvas.context.line_to(r.x, r.y);
-        canvas.context.stroke();
+        let r = Vector2d::new(0.0, radius).rotate(object.orient.to_f64()) + position;
+        frame.context.move_to(position.x, position.y);
+        frame.context.line_to(r.x, r.y);
+        frame.context.stroke();
     }
 }
 
 impl Circle {
-    pub fn new(x: f64, y: f64, r: f64) -> Circle {
+    pub fn new(x: Real, y: Real, r: Real) -> Circle {
         let mut c = Circle {
             radius: r,
             object: Rc::new(RefCell::new(Object::new(x, y))),
@@ -181,25 +403,160 @@ impl Circle {
     }
     fn initialize(&mut self) {
         let mut object = self.object.borrow_mut();
-        object.mass = PI * self.radius * self.radius * object.density;
-        object.inverse_mass = 1.0 / object.mass;
-        object.inertia = object.mass * self.radius * self.radius / 2.0;
-        object.inverse_inertia = 1.0 / object.inertia;
+        let mass = real(PI) * self.radius * self.radius * object.density;
+        object.set_mass(mass, mass * self.radius * self.radius / real(2.0));
     }
     pub fn set_static(&mut self) {
         self.object.borrow_mut().set_static();
     }
 }
 
+// A segment of length `2 * half_length` along the local x axis, swept by `radius`
+pub struct Capsule {
+    pub half_length: Real,
+    pub radius: Real,
+    pub object: Rc<RefCell<Object>>,
+}
+
+impl RigidBody for Capsule {
+    fn radius(&self) -> Real {
+        self.half_length + self.radius
+    }
+
+    fn set_density(&mut self, density: Real) {
+        self.object.borrow_mut().density = density;
+        self.initialize();
+    }
+
+    fn object(&self) -> Rc<RefCell<Object>> {
+        self.object.clone()
+    }
+
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
+    }
+
+    fn integrate_velocity(&mut self, dt: Real) {
+        self.object.borrow_mut().integrate_velocity(dt);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let object = self.object.borrow();
+        let mut position = object.position.to_f64();
+        position.x *= frame.scaled_width;
+        position.y *= frame.scaled_height;
+        let half_length = self.half_length.to_f64() * frame.scaled_width;
+        let radius = self.radius.to_f64() * frame.scaled_width;
+
+        // Each cap is half a circle, the straight sides connect them
+        let k_segments = 15;
+        let inc = PI / k_segments as f64;
+        let mut points = Vec::new();
+        for i in 0..=k_segments {
+            let theta = -PI / 2.0 + inc * i as f64;
+            points.push(Vector2d::new(
+                half_length + radius * theta.cos(),
+                radius * theta.sin(),
+            ));
+        }
+        for i in 0..=k_segments {
+            let theta = PI / 2.0 + inc * i as f64;
+            points.push(Vector2d::new(
+                -half_length + radius * theta.cos(),
+                radius * theta.sin(),
+            ));
+        }
+
+        frame.context.begin_path();
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            object.color.r, object.color.g, object.color.b
+        );
+        frame.context.set_stroke_style_color(&color);
+        let begin = points[0].rotate(object.orient.to_f64()) + position;
+        frame.context.move_to(begin.x, begin.y);
+        for &p in &points {
+            let point = p.rotate(object.orient.to_f64()) + position;
+            frame.context.line_to(point.x, point.y);
+        }
+        frame.context.close_path();
+        frame.context.stroke();
+
+        frame.context.fill_rect(
+            position.x,
+            position.y,
+            0.05 * frame.scaled_width,
+            0.05 * frame.scaled_width,
+        );
+    }
+}
+
+impl Capsule {
+    pub fn new(x: Real, y: Real, half_length: Real, radius: Real) -> Capsule {
+        let mut c = Capsule {
+            half_length,
+            radius,
+            object: Rc::new(RefCell::new(Object::new(x, y))),
+        };
+        c.initialize();
+        c
+    }
+    fn initialize(&mut self) {
+        let mut object = self.object.borrow_mut();
+        let length = real(2.0) * self.half_length;
+        let r2 = self.radius * self.radius;
+        let rectangle_mass = length * real(2.0) * self.radius * object.density;
+        let caps_mass = real(PI) * r2 * object.density;
+
+        // The two caps form a full circle, but each half sits at `half_length` plus the
+        // offset of its own centroid from the flat side
+        let cap_offset = real(4.0 / (3.0 * PI)) * self.radius;
+        let rectangle_inertia = rectangle_mass * (length * length + real(4.0) * r2) / real(12.0);
+        let caps_inertia = caps_mass
+            * (r2 / real(2.0)
+                + self.half_length * self.half_length
+                + real(2.0) * self.half_length * cap_offset);
+        object.set_mass(rectangle_mass + caps_mass, rectangle_inertia + caps_inertia);
+    }
+    // End points of the segment in world space
+    pub fn end_points(&self) -> (Vector2d<Real>, Vector2d<Real>) {
+        let object = self.object.borrow();
+        let axis = Vector2d::new(self.half_length, real(0.0)).rotate(object.orient);
+        (object.position - axis, object.position + axis)
+    }
+}
+
+// Mass, centroid and moment of inertia about the centroid of a polygon
+fn polygon_mass(vertices: &[Vector2d<Real>], density: Real) -> (Real, Vector2d<Real>, Real) {
+    let mut centroid = Vector2d::zero();
+    let mut area = real(0.0);
+    let mut inertia = real(0.0);
+    let n = vertices.len();
+    for i1 in 0..n {
+        // Triangle vertices, the third vertex is (0, 0)
+        let p1 = vertices[i1];
+        let i2 = if i1 + 1 < n { i1 + 1 } else { 0 };
+        let p2 = vertices[i2];
+        let triangle_area = real(0.5) * p1.cross_product(p2); // This might be negative, if (0, 0) is outside the polygon
+        area += triangle_area;
+        centroid += (p1 + p2) * (real(1.0 / 3.0) * triangle_area);
+        inertia +=
+            triangle_area * density * (p1.len_square() + p2.len_square() + p1 * p2) / real(6.0);
+    }
+    centroid /= area;
+    let mass = area * density;
+    (mass, centroid, inertia - mass * centroid.len_square())
+}
+
 pub struct Polygon {
-    pub radius: f64,
-    pub vertices: Vec<Vector2d<f64>>,
-    pub normals: Vec<Vector2d<f64>>,
+    pub radius: Real,
+    pub vertices: Vec<Vector2d<Real>>,
+    pub normals: Vec<Vector2d<Real>>,
     pub object: Rc<RefCell<Object>>,
 }
 
 impl Polygon {
-    pub fn get_support(&self, direction: Vector2d<f64>) -> Vector2d<f64> {
+    pub fn get_support(&self, direction: Vector2d<Real>) -> Vector2d<Real> {
         let mut best_projection = NEG_INFINITY;
         let mut best_vertex = Vector2d::zero();
         for &v in &self.vertices {
@@ -212,9 +569,7 @@ impl Polygon {
         best_vertex
     }
 
-    fn initialize(&mut self) {
-        let mut object = self.object.borrow_mut();
-        // Calculate face normals
+    fn compute_normals(&mut self) {
         self.normals.clear();
         let n = self.vertices.len();
         for i1 in 0..n {
@@ -223,150 +578,856 @@ impl Polygon {
             let normal = Vector2d::new(face.y, -face.x).normalize();
             self.normals.push(normal);
         }
+    }
 
-        // Calculate centroid and moment of interia
-        let mut centroid = Vector2d::zero();
-        let mut area = 0.0;
-        let mut inertia = 0.0;
-        let n = self.vertices.len();
-        for i1 in 0..n {
-            // Triangle vertices, the third vertex is (0, 0)
-            let p1 = self.vertices[i1];
-            let i2 = if i1 + 1 < n { i1 + 1 } else { 0 };
-            let p2 = self.vertices[i2];
-            let triangle_area = 0.5 * p1.cross_product(p2); // This might be negative, if (0, 0) is outside the polygon
-            area += triangle_area;
-            centroid += (p1 + p2) * (1.0 / 3.0 * triangle_area);
-            inertia +=
-                triangle_area * object.density * (p1.len_square() + p2.len_square() + p1 * p2)
-                    / 6.0;
-        }
-        centroid /= area;
-        // console!(log, "centroid: %f, %f", centroid.x, centroid.y);
+    // Returns the centroid of the vertices, which becomes the new origin
+    fn initialize(&mut self) -> Vector2d<Real> {
+        // Calculate face normals
+        self.compute_normals();
+        let mut object = self.object.borrow_mut();
+        let (mass, centroid, inertia) = polygon_mass(&self.vertices, object.density);
 
         // Make the centroid (0, 0)
         for v in &mut self.vertices {
             *v -= centroid;
         }
-
-        // Calculate mass and interia
-        object.mass = area * object.density;
-        object.inverse_mass = 1.0 / object.mass;
-        object.inertia = inertia - object.mass * centroid.len_square();
-        // console!(log, "inertia: %f", object.inertia);
-        object.inverse_inertia = 1.0 / object.inertia;
+        object.set_mass(mass, inertia);
+        centroid
     }
 
     pub fn set_static(&mut self) {
         self.object.borrow_mut().set_static();
     }
 
-    pub fn set_vertices(&mut self, vertices: &Vec<Vector2d<f64>>) {
-        self.vertices = vertices.clone();
+    pub fn set_vertices(&mut self, vertices: &[Vector2d<Real>]) -> Result<(), PolygonError> {
+        validate_convex(vertices)?;
+        self.vertices = vertices.to_vec();
         self.initialize();
+        Ok(())
     }
 
-    pub fn new(x: f64, y: f64, r: f64) -> Self {
-        let mut rng = Rng::new();
-        let count = rng.gen_range(3, 64);
-        let mut vertices = Vec::new();
-        for _ in 0..count {
-            let x = (rng.gen_range(0, 20000) - 10000) as f64 / 10000.0 * r as f64;
-            let y = (rng.gen_range(0, 20000) - 10000) as f64 / 10000.0 * r as f64;
-            vertices.push(Vector2d::new(x, y));
-        }
-        // Test these vertices to ensure it is really a convex polygon
-        let mut right_most = vertices[0];
-        for &v in &vertices {
-            if v.x > right_most.x {
-                right_most = v;
-            } else if v.x == right_most.x {
-                if v.y < right_most.y {
-                    right_most = v;
-                }
-            }
-        }
-        let mut result_vertices = Vec::new();
-        result_vertices.push(right_most);
-        let mut next_index = vertices[0].clone();
-        let mut index = right_most;
-        loop {
-            for &v in &vertices {
-                if next_index == index {
-                    next_index = v;
-                }
-                let e1 = next_index - index;
-                let e2 = v - index;
-                let c = e1.cross_product(e2);
-                if c < 0.0 {
-                    next_index = v;
-                } else if c == 0.0 && e2.len_square() > e1.len_square() {
-                    next_index = v;
-                }
+    pub fn new(x: Real, y: Real, r: Real, rng: &mut Rng) -> Self {
+        // Random points can be collinear, try again until their hull has an area
+        let vertices = loop {
+            let count = rng.gen_range(3, 64);
+            let mut points = Vec::new();
+            for _ in 0..count {
+                let x = real((rng.gen_range(0, 20000) - 10000) as f64 / 10000.0) * r;
+                let y = real((rng.gen_range(0, 20000) - 10000) as f64 / 10000.0) * r;
+                points.push(Vector2d::new(x, y));
             }
-
-            if next_index == right_most {
-                break;
+            let hull = convex_hull(&points);
+            if validate_convex(&hull).is_ok() {
+                break hull;
             }
-
-            result_vertices.push(next_index);
-            index = next_index;
-        }
+        };
 
         let mut polygon = Self {
-            radius: r * 1.5,
-            vertices: result_vertices,
+            radius: r * real(1.5),
+            vertices,
             normals: Vec::new(),
             object: Rc::new(RefCell::new(Object::new(x, y))),
         };
         polygon.initialize();
         polygon
     }
+
+    // A rectangle rotated by `angle`, centered at `center`
+    pub fn new_box(
+        center: Vector2d<Real>,
+        half_width: Real,
+        half_height: Real,
+        angle: Real,
+    ) -> Result<Self, PolygonError> {
+        let valid = |extent: Real| extent > real(0.0) && extent.is_finite();
+        if !valid(half_width) || !valid(half_height) {
+            return Err(PolygonError::InvalidDimensions);
+        }
+        let vertices = [
+            Vector2d::new(-half_width, -half_height),
+            Vector2d::new(half_width, -half_height),
+            Vector2d::new(half_width, half_height),
+            Vector2d::new(-half_width, half_height),
+        ];
+        let polygon = Self::from_vertices(center, &vertices)?;
+        polygon.object.borrow_mut().orient = angle;
+        Ok(polygon)
+    }
+
+    // A polygon with `sides` equal edges whose vertices lie on a circle of `radius`
+    pub fn new_regular(
+        center: Vector2d<Real>,
+        radius: Real,
+        sides: usize,
+    ) -> Result<Self, PolygonError> {
+        if radius <= real(0.0) || !radius.is_finite() {
+            return Err(PolygonError::InvalidDimensions);
+        }
+        let inc = real(2.0 * PI / sides as f64);
+        let vertices: Vec<_> = (0..sides)
+            .map(|i| Vector2d::new(radius, real(0.0)).rotate(inc * real(i as f64)))
+            .collect();
+        Self::from_vertices(center, &vertices)
+    }
+
+    // The convex hull of arbitrary points given relative to `center`
+    pub fn from_points(
+        center: Vector2d<Real>,
+        points: &[Vector2d<Real>],
+    ) -> Result<Self, PolygonError> {
+        Self::from_vertices(center, &convex_hull(points))
+    }
+
+    // Build a polygon from convex vertices given relative to `center`, the body is placed so
+    // that its vertices end up exactly at `center + vertices[i]` in world space.
+    // Vertices are counter-clockwise with y pointing up, which is clockwise on the canvas.
+    pub fn from_vertices(
+        center: Vector2d<Real>,
+        vertices: &[Vector2d<Real>],
+    ) -> Result<Self, PolygonError> {
+        validate_convex(vertices)?;
+        let mut polygon = Self {
+            radius: real(0.0),
+            vertices: vertices.to_vec(),
+            normals: Vec::new(),
+            object: Rc::new(RefCell::new(Object::new(center.x, center.y))),
+        };
+        let centroid = polygon.initialize();
+        polygon.object.borrow_mut().position += centroid;
+        polygon.radius = polygon
+            .vertices
+            .iter()
+            .map(|v| v.len())
+            .fold(real(0.0), Real::max);
+        Ok(polygon)
+    }
 }
 
 impl RigidBody for Polygon {
-    fn radius(&self) -> f64 {
+    fn radius(&self) -> Real {
         return self.radius;
     }
 
+    fn set_density(&mut self, density: Real) {
+        let mut object = self.object.borrow_mut();
+        object.density = density;
+        let (mass, _, inertia) = polygon_mass(&self.vertices, density);
+        object.set_mass(mass, inertia);
+    }
+
     fn object(&self) -> Rc<RefCell<Object>> {
         self.object.clone()
     }
 
-    fn integrate_forces(&mut self, dt: f64) {
-        self.object.borrow_mut().integrate_forces(dt);
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
     }
 
-    fn integrate_velocity(&mut self, dt: f64) {
+    fn integrate_velocity(&mut self, dt: Real) {
         self.object.borrow_mut().integrate_velocity(dt);
     }
 
-    fn draw(&self, canvas: &mut Canvas) {
+    fn draw(&self, frame: &mut Frame) {
         let object = self.object.borrow();
-        let mut position = object.position;
-        position.x *= canvas.scaled_width;
-        position.y *= canvas.scaled_height;
+        let mut position = object.position.to_f64();
+        position.x *= frame.scaled_width;
+        position.y *= frame.scaled_height;
 
-        let begin = (self.vertices[0] * canvas.scaled_width).rotate(object.orient) + position;
-        canvas.context.begin_path();
+        let begin = (self.vertices[0].to_f64() * frame.scaled_width).rotate(object.orient.to_f64())
+            + position;
+        frame.context.begin_path();
         let color = format!(
             "#{:02x}{:02x}{:02x}",
             object.color.r, object.color.g, object.color.b
         );
-        canvas.context.set_stroke_style_color(&color);
-        canvas.context.move_to(begin.x, begin.y);
+        frame.context.set_stroke_style_color(&color);
+        frame.context.move_to(begin.x, begin.y);
         for &v in &self.vertices {
-            let point = (v * canvas.scaled_width).rotate(object.orient) + position;
-            canvas.context.line_to(point.x, point.y);
+            let point = (v.to_f64() * frame.scaled_width).rotate(object.orient.to_f64()) + position;
+            frame.context.line_to(point.x, point.y);
         }
-        canvas.context.close_path();
-        canvas.context.stroke();
+        frame.context.close_path();
+        frame.context.stroke();
 
-        canvas.context.fill_rect(
+        frame.context.fill_rect(
             position.x,
             position.y,
-            0.05 * canvas.scaled_width,
-            0.05 * canvas.scaled_width,
+            0.05 * frame.scaled_width,
+            0.05 * frame.scaled_width,
         );
     }
 }
+
+// A body made of convex pieces, which all share the compound's object. Either a concave outline
+// split into pieces, or pieces given one by one, which may overlap like the fixtures of a Box2D
+// body. `outline` is empty for the latter.
+pub struct Compound {
+    pub radius: Real,
+    pub outline: Vec<Vector2d<Real>>,
+    pub pieces: Vec<Polygon>,
+    pub object: Rc<RefCell<Object>>,
+}
+
+impl Compound {
+    // Build a body from a simple outline given relative to `center`, in either winding.
+    // Like `Polygon::from_vertices`, the outline ends up exactly at `center + vertices[i]`.
+    pub fn from_vertices(
+        center: Vector2d<Real>,
+        vertices: &[Vector2d<Real>],
+    ) -> Result<Self, PolygonError> {
+        let pieces = decompose(vertices)?;
+        let mut outline = vertices.to_vec();
+        if signed_area(&outline) < real(0.0) {
+            outline.reverse();
+        }
+        let (_, centroid, _) = polygon_mass(&outline, real(1.0));
+        Ok(Self::assemble(center, centroid, outline, pieces))
+    }
+
+    // Build a body from convex pieces given relative to `center`, each one like the vertices of
+    // `Polygon::from_vertices`. Overlapping parts count once for every piece they belong to.
+    pub fn from_pieces(
+        center: Vector2d<Real>,
+        pieces: &[Vec<Vector2d<Real>>],
+    ) -> Result<Self, PolygonError> {
+        if pieces.is_empty() {
+            return Err(PolygonError::TooFewVertices);
+        }
+        let mut area = real(0.0);
+        let mut centroid = Vector2d::zero();
+        for piece in pieces {
+            validate_convex(piece)?;
+            let (piece_area, piece_centroid, _) = polygon_mass(piece, real(1.0));
+            area += piece_area;
+            centroid += piece_centroid * piece_area;
+        }
+        centroid /= area;
+        Ok(Self::assemble(
+            center,
+            centroid,
+            Vec::new(),
+            pieces.to_vec(),
+        ))
+    }
+
+    // Make the centroid (0, 0) and place the body there
+    fn assemble(
+        center: Vector2d<Real>,
+        centroid: Vector2d<Real>,
+        mut outline: Vec<Vector2d<Real>>,
+        pieces: Vec<Vec<Vector2d<Real>>>,
+    ) -> Self {
+        for v in &mut outline {
+            *v -= centroid;
+        }
+        let object = Rc::new(RefCell::new(Object::new(center.x, center.y)));
+        object.borrow_mut().position += centroid;
+
+        let pieces: Vec<_> = pieces
+            .into_iter()
+            .map(|vertices| {
+                let vertices: Vec<_> = vertices.iter().map(|&v| v - centroid).collect();
+                let mut piece = Polygon {
+                    radius: vertices.iter().map(|v| v.len()).fold(real(0.0), Real::max),
+                    vertices,
+                    normals: Vec::new(),
+                    object: object.clone(),
+                };
+                piece.compute_normals();
+                piece
+            })
+            .collect();
+        let mut compound = Self {
+            radius: pieces.iter().map(|p| p.radius).fold(real(0.0), Real::max),
+            outline,
+            pieces,
+            object,
+        };
+        let density = compound.object.borrow().density;
+        compound.set_density(density);
+        compound
+    }
+
+    pub fn set_static(&mut self) {
+        self.object.borrow_mut().set_static();
+    }
+}
+
+impl RigidBody for Compound {
+    fn radius(&self) -> Real {
+        self.radius
+    }
+
+    // The sum of the pieces, which is the whole outline when there is one
+    fn set_density(&mut self, density: Real) {
+        let mut mass = real(0.0);
+        let mut inertia = real(0.0);
+        for piece in &self.pieces {
+            let (piece_mass, centroid, piece_inertia) = polygon_mass(&piece.vertices, density);
+            mass += piece_mass;
+            inertia += piece_inertia + piece_mass * centroid.len_square();
+        }
+        let mut object = self.object.borrow_mut();
+        object.density = density;
+        object.set_mass(mass, inertia);
+    }
+
+    fn object(&self) -> Rc<RefCell<Object>> {
+        self.object.clone()
+    }
+
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
+    }
+
+    fn integrate_velocity(&mut self, dt: Real) {
+        self.object.borrow_mut().integrate_velocity(dt);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let object = self.object.borrow();
+        let mut position = object.position.to_f64();
+        position.x *= frame.scaled_width;
+        position.y *= frame.scaled_height;
+
+        frame.context.begin_path();
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            object.color.r, object.color.g, object.color.b
+        );
+        frame.context.set_stroke_style_color(&color);
+        // Pieces given one by one are drawn one by one
+        let outlines: Vec<&[Vector2d<Real>]> = if self.outline.is_empty() {
+            self.pieces.iter().map(|p| &p.vertices[..]).collect()
+        } else {
+            vec![&self.outline]
+        };
+        for outline in outlines {
+            let begin = (outline[0].to_f64() * frame.scaled_width).rotate(object.orient.to_f64())
+                + position;
+            frame.context.move_to(begin.x, begin.y);
+            for &v in outline {
+                let point =
+                    (v.to_f64() * frame.scaled_width).rotate(object.orient.to_f64()) + position;
+                frame.context.line_to(point.x, point.y);
+            }
+            frame.context.close_path();
+        }
+        frame.context.stroke();
+
+        frame.context.fill_rect(
+            position.x,
+            position.y,
+            0.05 * frame.scaled_width,
+            0.05 * frame.scaled_width,
+        );
+    }
+}
+
+// A static segment with no thickness. The optional ghost vertices are the neighbouring
+// vertices of the terrain it belongs to, which keep bodies from catching on inner joints.
+pub struct Edge {
+    pub v0: Option<Vector2d<Real>>,
+    pub v1: Vector2d<Real>,
+    pub v2: Vector2d<Real>,
+    pub v3: Option<Vector2d<Real>>,
+    pub object: Rc<RefCell<Object>>,
+}
+
+impl Edge {
+    // Vertices are given in world space and must be apart, a zero-length edge has no normal
+    pub fn new(v1: Vector2d<Real>, v2: Vector2d<Real>) -> Result<Self, PolygonError> {
+        if has_zero_length_edge(&[v1, v2]) {
+            return Err(PolygonError::DegenerateEdge);
+        }
+        let center = (v1 + v2) / real(2.0);
+        let mut object = Object::new(center.x, center.y);
+        object.set_static();
+        Ok(Self {
+            v0: None,
+            v1: v1 - center,
+            v2: v2 - center,
+            v3: None,
+            object: Rc::new(RefCell::new(object)),
+        })
+    }
+
+    pub fn set_ghost_vertices(&mut self, v0: Option<Vector2d<Real>>, v3: Option<Vector2d<Real>>) {
+        let position = self.object.borrow().position;
+        self.v0 = v0.map(|v| v - position);
+        self.v3 = v3.map(|v| v - position);
+    }
+
+    // Ghost vertices and end points in world space
+    pub fn world_vertices(&self) -> WorldEdge {
+        let object = self.object.borrow();
+        let to_world = |v: Vector2d<Real>| v.rotate(object.orient) + object.position;
+        WorldEdge {
+            v0: self.v0.map(to_world),
+            v1: to_world(self.v1),
+            v2: to_world(self.v2),
+            v3: self.v3.map(to_world),
+        }
+    }
+}
+
+// The vertices of an edge in world space, named as in `Edge`
+#[derive(Clone, Copy)]
+pub struct WorldEdge {
+    pub v0: Option<Vector2d<Real>>,
+    pub v1: Vector2d<Real>,
+    pub v2: Vector2d<Real>,
+    pub v3: Option<Vector2d<Real>>,
+}
+
+impl RigidBody for Edge {
+    fn radius(&self) -> Real {
+        (self.v2 - self.v1).len() / real(2.0)
+    }
+
+    // Edges are always static
+    fn set_density(&mut self, density: Real) {
+        self.object.borrow_mut().density = density;
+    }
+
+    fn object(&self) -> Rc<RefCell<Object>> {
+        self.object.clone()
+    }
+
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
+    }
+
+    fn integrate_velocity(&mut self, dt: Real) {
+        self.object.borrow_mut().integrate_velocity(dt);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let object = self.object.borrow();
+        let WorldEdge { v1, v2, .. } = self.world_vertices();
+        let (v1, v2) = (v1.to_f64(), v2.to_f64());
+        frame.context.begin_path();
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            object.color.r, object.color.g, object.color.b
+        );
+        frame.context.set_stroke_style_color(&color);
+        frame
+            .context
+            .move_to(v1.x * frame.scaled_width, v1.y * frame.scaled_height);
+        frame
+            .context
+            .line_to(v2.x * frame.scaled_width, v2.y * frame.scaled_height);
+        frame.context.stroke();
+    }
+}
+
+// A static polyline made of edges, optionally closed into a loop. Every edge knows its
+// neighbours as ghost vertices, so bodies slide smoothly across the joints.
+pub struct Chain {
+    pub vertices: Vec<Vector2d<Real>>,
+    pub is_loop: bool,
+    pub radius: Real,
+    pub object: Rc<RefCell<Object>>,
+}
+
+impl Chain {
+    // Vertices are given in world space, at least 2 of them and no two consecutive ones equal
+    pub fn new(vertices: &[Vector2d<Real>]) -> Result<Self, PolygonError> {
+        if vertices.len() < 2 {
+            return Err(PolygonError::TooFewVertices);
+        }
+        if vertices.windows(2).any(has_zero_length_edge) {
+            return Err(PolygonError::DegenerateEdge);
+        }
+        Ok(Self::with_vertices(vertices, false))
+    }
+
+    // Like `new`, with at least 3 vertices and an edge from the last one back to the first
+    pub fn new_loop(vertices: &[Vector2d<Real>]) -> Result<Self, PolygonError> {
+        if vertices.len() < 3 {
+            return Err(PolygonError::TooFewVertices);
+        }
+        if has_zero_length_edge(vertices) {
+            return Err(PolygonError::DegenerateEdge);
+        }
+        Ok(Self::with_vertices(vertices, true))
+    }
+
+    fn with_vertices(vertices: &[Vector2d<Real>], is_loop: bool) -> Self {
+        let mut center = Vector2d::zero();
+        for &v in vertices {
+            center += v;
+        }
+        center /= real(vertices.len() as f64);
+        let vertices: Vec<_> = vertices.iter().map(|&v| v - center).collect();
+        let radius = vertices.iter().map(|v| v.len()).fold(real(0.0), Real::max);
+        let mut object = Object::new(center.x, center.y);
+        object.set_static();
+        Self {
+            vertices,
+            is_loop,
+            radius,
+            object: Rc::new(RefCell::new(object)),
+        }
+    }
+
+    // The edges of this chain in local space, sharing the chain's object
+    pub fn edges(&self) -> impl Iterator<Item = Edge> + '_ {
+        let n = self.vertices.len();
+        let count = if self.is_loop { n } else { n - 1 };
+        (0..count).map(move |i| {
+            let previous = if i > 0 {
+                Some(self.vertices[i - 1])
+            } else if self.is_loop {
+                Some(self.vertices[n - 1])
+            } else {
+                None
+            };
+            let next = if i + 2 < n || self.is_loop {
+                Some(self.vertices[(i + 2) % n])
+            } else {
+                None
+            };
+            Edge {
+                v0: previous,
+                v1: self.vertices[i],
+                v2: self.vertices[(i + 1) % n],
+                v3: next,
+                object: self.object.clone(),
+            }
+        })
+    }
+}
+
+impl RigidBody for Chain {
+    fn radius(&self) -> Real {
+        self.radius
+    }
+
+    // Chains are always static
+    fn set_density(&mut self, density: Real) {
+        self.object.borrow_mut().density = density;
+    }
+
+    fn object(&self) -> Rc<RefCell<Object>> {
+        self.object.clone()
+    }
+
+    fn integrate_forces(&mut self, gravity: Vector2d<Real>, dt: Real) {
+        self.object.borrow_mut().integrate_forces(gravity, dt);
+    }
+
+    fn integrate_velocity(&mut self, dt: Real) {
+        self.object.borrow_mut().integrate_velocity(dt);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let object = self.object.borrow();
+        let mut position = object.position.to_f64();
+        position.x *= frame.scaled_width;
+        position.y *= frame.scaled_height;
+
+        let begin = (self.vertices[0].to_f64() * frame.scaled_width).rotate(object.orient.to_f64())
+            + position;
+        frame.context.begin_path();
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            object.color.r, object.color.g, object.color.b
+        );
+        frame.context.set_stroke_style_color(&color);
+        frame.context.move_to(begin.x, begin.y);
+        for &v in &self.vertices {
+            let point = (v.to_f64() * frame.scaled_width).rotate(object.orient.to_f64()) + position;
+            frame.context.line_to(point.x, point.y);
+        }
+        if self.is_loop {
+            frame.context.close_path();
+        }
+        frame.context.stroke();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+    use crate::scene::Scene;
+
+    #[test]
+    fn capsule_mass_matches_the_closed_form() {
+        for &(half_length, radius, density) in &[(1.0, 0.5, 1.0), (0.3, 0.2, 2.5), (0.0, 1.0, 1.0)]
+        {
+            let mut capsule = Capsule::new(real(0.0), real(0.0), real(half_length), real(radius));
+            capsule.set_density(real(density));
+            let object = capsule.object.borrow();
+            let (h, r) = (half_length, radius);
+            let length = 2.0 * h;
+            let pi = std::f64::consts::PI;
+            // A rectangle and a disc split at the middle and pushed out to the ends
+            let mass = density * (2.0 * r * length + pi * r * r);
+            let inertia = density
+                * (2.0 * r * length * (length * length + 4.0 * r * r) / 12.0
+                    + pi * r * r * (r * r / 2.0 + h * h)
+                    + 8.0 * r * r * r * h / 3.0);
+            assert!((object.mass.to_f64() - mass).abs() < 1e-3 * mass);
+            assert!((object.inertia.to_f64() - inertia).abs() < 1e-3 * inertia);
+            assert!((object.inverse_mass.to_f64() * mass - 1.0).abs() < 1e-3);
+        }
+        // Without a segment it is a circle
+        let capsule = Capsule::new(real(0.0), real(0.0), real(0.0), real(1.0));
+        let circle = Circle::new(real(0.0), real(0.0), real(1.0));
+        let (a, b) = (capsule.object.borrow(), circle.object.borrow());
+        assert!((a.mass - b.mass).abs().to_f64() < 1e-3);
+        assert!((a.inertia - b.inertia).abs().to_f64() < 1e-3);
+    }
+
+    #[test]
+    fn box_and_regular_polygon_reject_bad_dimensions() {
+        let center = Vector2d::new(real(0.0), real(0.0));
+        let error = Some(PolygonError::InvalidDimensions);
+        assert_eq!(
+            Polygon::new_box(center, real(-1.0), real(1.0), real(0.0)).err(),
+            error
+        );
+        assert_eq!(
+            Polygon::new_box(center, real(1.0), real(0.0), real(0.0)).err(),
+            error
+        );
+        assert_eq!(Polygon::new_regular(center, real(-1.0), 5).err(), error);
+        assert!(Polygon::new_box(center, real(1.0), real(0.5), real(0.3)).is_ok());
+    }
+
+    fn point(x: f64, y: f64) -> Vector2d<Real> {
+        Vector2d::new(x, y).to_real()
+    }
+
+    // Where the vertices of `polygon` are in the world
+    fn world_vertices(polygon: &Polygon) -> Vec<Vector2d<f64>> {
+        let object = polygon.object.borrow();
+        polygon
+            .vertices
+            .iter()
+            .map(|v| (v.rotate(object.orient) + object.position).to_f64())
+            .collect()
+    }
+
+    fn assert_vertices(polygon: &Polygon, expected: &[(f64, f64)]) {
+        let vertices = world_vertices(polygon);
+        assert_eq!(vertices.len(), expected.len(), "{:?}", vertices);
+        for (v, &(x, y)) in vertices.iter().zip(expected) {
+            assert!((*v - Vector2d::new(x, y)).len() < 1e-3, "{:?}", vertices);
+        }
+        assert!(signed_area(&polygon.vertices) > real(0.0));
+    }
+
+    // The centroid of the local vertices is the body's origin
+    fn assert_centered(polygon: &Polygon) {
+        let centroid = polygon_mass(&polygon.vertices, real(1.0)).1;
+        assert!(centroid.to_f64().len() < 1e-3, "{:?}", centroid);
+    }
+
+    #[test]
+    fn box_vertices_surround_the_center() {
+        let block = Polygon::new_box(point(2.0, 3.0), real(1.0), real(0.5), real(0.0)).unwrap();
+        assert_eq!(block.object.borrow().position, point(2.0, 3.0));
+        assert_vertices(&block, &[(1.0, 2.5), (3.0, 2.5), (3.0, 3.5), (1.0, 3.5)]);
+        assert_centered(&block);
+
+        // A quarter turn stands the box upright
+        let angle = real(std::f64::consts::FRAC_PI_2);
+        let block = Polygon::new_box(point(2.0, 3.0), real(1.0), real(0.5), angle).unwrap();
+        assert_vertices(&block, &[(2.5, 2.0), (2.5, 4.0), (1.5, 4.0), (1.5, 2.0)]);
+    }
+
+    #[test]
+    fn regular_polygon_vertices_lie_on_the_circle() {
+        let hexagon = Polygon::new_regular(point(1.0, 1.0), real(2.0), 6).unwrap();
+        let h = 3f64.sqrt();
+        assert_vertices(
+            &hexagon,
+            &[
+                (3.0, 1.0),
+                (2.0, 1.0 + h),
+                (0.0, 1.0 + h),
+                (-1.0, 1.0),
+                (0.0, 1.0 - h),
+                (2.0, 1.0 - h),
+            ],
+        );
+        assert_centered(&hexagon);
+        assert!((hexagon.radius.to_f64() - 2.0).abs() < 1e-3);
+
+        assert_eq!(
+            Polygon::new_regular(point(0.0, 0.0), real(1.0), 2).err(),
+            Some(PolygonError::TooFewVertices)
+        );
+        assert_eq!(
+            Polygon::new_regular(point(0.0, 0.0), real(0.0), 5).err(),
+            Some(PolygonError::InvalidDimensions)
+        );
+    }
+
+    #[test]
+    fn polygon_from_vertices_moves_the_body_to_the_centroid() {
+        let vertices = [point(0.0, 0.0), point(3.0, 0.0), point(0.0, 3.0)];
+        let triangle = Polygon::from_vertices(point(1.0, 1.0), &vertices).unwrap();
+        // The vertices stay where they were given, the body sits at their centroid
+        assert_vertices(&triangle, &[(1.0, 1.0), (4.0, 1.0), (1.0, 4.0)]);
+        assert_centered(&triangle);
+        let position = triangle.object.borrow().position.to_f64();
+        assert!((position - Vector2d::new(2.0, 2.0)).len() < 1e-3);
+
+        let center = point(0.0, 0.0);
+        let error = |vertices: &[(f64, f64)]| {
+            let vertices: Vec<_> = vertices.iter().map(|&(x, y)| point(x, y)).collect();
+            Polygon::from_vertices(center, &vertices).err()
+        };
+        assert_eq!(
+            error(&[(0.0, 0.0), (1.0, 0.0)]),
+            Some(PolygonError::TooFewVertices)
+        );
+        assert_eq!(
+            error(&[(0.0, 0.0), (0.0, 1.0), (1.0, 0.0)]),
+            Some(PolygonError::WrongWinding)
+        );
+        assert_eq!(
+            error(&[(0.0, 0.0), (2.0, 0.0), (1.0, 0.5), (1.0, 2.0)]),
+            Some(PolygonError::NonConvex)
+        );
+        assert_eq!(
+            error(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (1.0, 1.0)]),
+            Some(PolygonError::DegenerateEdge)
+        );
+    }
+
+    #[test]
+    fn polygon_from_points_uses_their_hull() {
+        // Shuffled corners of a square with an inner point, a duplicate and a point on an edge
+        let points = [
+            point(1.0, 1.0),
+            point(-1.0, -1.0),
+            point(0.0, 0.0),
+            point(1.0, -1.0),
+            point(1.0, 1.0),
+            point(0.0, 1.0),
+            point(-1.0, 1.0),
+        ];
+        let square = Polygon::from_points(point(5.0, 5.0), &points).unwrap();
+        assert_vertices(&square, &[(4.0, 4.0), (6.0, 4.0), (6.0, 6.0), (4.0, 6.0)]);
+        assert_centered(&square);
+
+        let line = [point(0.0, 0.0), point(1.0, 1.0), point(2.0, 2.0)];
+        assert_eq!(
+            Polygon::from_points(point(0.0, 0.0), &line).err(),
+            Some(PolygonError::TooFewVertices)
+        );
+        assert_eq!(
+            Polygon::from_points(point(0.0, 0.0), &[]).err(),
+            Some(PolygonError::TooFewVertices)
+        );
+    }
+
+    #[test]
+    fn mass_changes_keep_the_shape_in_place() {
+        let mut circle = Circle::new(real(2.0), real(3.0), real(1.0));
+        let position = circle.object.borrow().position;
+        circle.object.borrow_mut().orient = real(0.5);
+        let center = Vector2d::new(real(0.25), real(-0.5));
+        circle
+            .object
+            .borrow_mut()
+            .set_mass_data(real(2.0), center, real(0.75))
+            .unwrap();
+        {
+            let object = circle.object.borrow();
+            assert_eq!(object.position, position);
+            assert_eq!(object.center, center);
+            assert_eq!(object.world_center(), position + center.rotate(real(0.5)));
+        }
+
+        // Recomputing the mass moves the center of mass back to the shape's origin
+        circle.set_density(real(3.0));
+        let object = circle.object.borrow();
+        assert_eq!(object.position, position);
+        assert_eq!(object.center, Vector2d::zero());
+        assert_eq!(object.world_center(), position);
+    }
+
+    #[test]
+    fn invalid_mass_data_is_rejected_without_changes() {
+        let circle = Circle::new(real(0.0), real(0.0), real(1.0));
+        let mut object = circle.object.borrow_mut();
+        let (mass, inertia) = (object.mass, object.inertia);
+        let zero = Vector2d::zero();
+        assert_eq!(
+            object.set_mass_data(real(0.0), zero, real(1.0)),
+            Err(MassDataError::InvalidMass)
+        );
+        assert_eq!(
+            object.set_mass_data(real(1.0), zero, real(-1.0)),
+            Err(MassDataError::InvalidInertia)
+        );
+        assert_eq!(object.mass, mass);
+        assert_eq!(object.inertia, inertia);
+    }
+
+    #[test]
+    fn chains_and_edges_reject_degenerate_segments() {
+        let point = |x: f64, y: f64| Vector2d::new(x, y).to_real();
+        let (a, b, c) = (point(0.0, 0.0), point(1.0, 0.0), point(1.0, 1.0));
+        assert_eq!(Edge::new(a, a).err(), Some(PolygonError::DegenerateEdge));
+        assert!(Edge::new(a, b).is_ok());
+        assert_eq!(Chain::new(&[a]).err(), Some(PolygonError::TooFewVertices));
+        assert_eq!(
+            Chain::new(&[a, b, b, c]).err(),
+            Some(PolygonError::DegenerateEdge)
+        );
+        assert_eq!(
+            Chain::new_loop(&[a, b]).err(),
+            Some(PolygonError::TooFewVertices)
+        );
+        assert_eq!(
+            Chain::new_loop(&[a, b, c, a]).err(),
+            Some(PolygonError::DegenerateEdge)
+        );
+        // An open chain may end where it started
+        assert_eq!(Chain::new(&[a, b, c, a]).unwrap().edges().count(), 3);
+        assert_eq!(Chain::new_loop(&[a, b, c]).unwrap().edges().count(), 3);
+    }
+
+    #[test]
+    fn box_slides_across_chain_joints() {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        let ice = Material::new("ice", real(0.0), real(0.0), real(0.0));
+        // Tiles a quarter wide, so the box crosses a joint every few steps
+        let ground: Vec<_> = (0..=72)
+            .map(|i| Vector2d::new(1.0 + 0.25 * i as f64, 15.0).to_real())
+            .collect();
+        let chain = Chain::new(&ground).unwrap();
+        chain.object.borrow_mut().material = ice.clone();
+        scene.add_body(Box::new(chain));
+
+        let block = Polygon::new_box(
+            Vector2d::new(3.0, 14.5).to_real(),
+            real(0.5),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        let object = block.object.clone();
+        object.borrow_mut().material = ice;
+        object.borrow_mut().velocity = Vector2d::new(real(2.0), real(0.0));
+        scene.add_body(Box::new(block));
+
+        // A snag would slow the box down, lift it or tip it over
+        for _ in 0..150 {
+            scene.step();
+            let object = object.borrow();
+            assert!(object.velocity.x > real(1.9), "{:?}", object.velocity);
+            assert!(object.velocity.y.abs() < real(0.2), "{:?}", object.velocity);
+            assert!(object.orient.abs() < real(0.01));
+        }
+        assert!(object.borrow().position.x > real(7.5));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::math::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+const FRACTION_BITS: u32 = 32;
+const ONE: i64 = 1 << FRACTION_BITS;
+
+// Q32.32 fixed-point number. Every operation the solver uses, trigonometry included, is integer
+// arithmetic, so results are the same on every platform. Only converting from and to `f64`, for
+// constants, files and drawing, uses floats. Overflows saturate, and the extreme values stand
+// for the infinities. It takes 8 bytes like an `f64`, so it is about determinism and not memory.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Hash, Default)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const MAX: Fixed = Fixed(i64::MAX);
+    pub const MIN: Fixed = Fixed(i64::MIN);
+
+    pub fn from_bits(bits: i64) -> Self {
+        Fixed(bits)
+    }
+    pub fn to_bits(self) -> i64 {
+        self.0
+    }
+}
+
+// Angles in Q2.61 for the CORDIC rotation, which keeps 29 more bits than the result needs
+const ANGLE_BITS: u32 = 61;
+const ANGLE_PI: i128 = 7_244_019_458_077_122_842;
+const ANGLE_TWO_PI: i128 = 14_488_038_916_154_245_685;
+// 1 / (sqrt(1 + 2^-0) * sqrt(1 + 2^-2) * sqrt(1 + 2^-4) * ...), the gain of the iterations
+const CORDIC_GAIN: i64 = 1_400_229_935_014_726_477;
+const CORDIC_ITERATIONS: usize = 40;
+// atan(2^-i) in Q2.61. From here on it rounds to 2^-i itself.
+const ATAN: [i64; 21] = [
+    1_811_004_864_519_280_711,
+    1_069_098_597_953_152_948,
+    564_882_337_777_596_249,
+    286_743_094_836_456_889,
+    143_927_976_672_616_092,
+    72_034_151_524_184_357,
+    36_025_865_417_378_411,
+    18_014_032_019_027_246,
+    9_007_153_442_175_927,
+    4_503_593_900_760_542,
+    2_251_799_097_857_775,
+    1_125_899_817_364_151,
+    562_949_942_236_502,
+    281_474_975_312_555,
+    140_737_488_180_565,
+    70_368_744_155_819,
+    35_184_372_086_101,
+    17_592_186_044_075,
+    8_796_093_022_165,
+    4_398_046_511_099,
+    2_199_023_255_551,
+];
+
+fn saturate(x: i128) -> Fixed {
+    Fixed(x.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64)
+}
+
+impl Fixed {
+    // Sine and cosine by CORDIC: reduce the angle to [-pi/2, pi/2], then rotate (gain, 0)
+    // towards it by +-atan(2^-i) steps, which only need shifts and additions
+    pub fn sin_cos(self) -> (Fixed, Fixed) {
+        let extra_bits = ANGLE_BITS - FRACTION_BITS;
+        let mut angle = (i128::from(self.0) << extra_bits).rem_euclid(ANGLE_TWO_PI);
+        if angle > ANGLE_PI {
+            angle -= ANGLE_TWO_PI;
+        }
+        let mut flip_cos = false;
+        if angle > ANGLE_PI / 2 {
+            angle = ANGLE_PI - angle;
+            flip_cos = true;
+        } else if angle < -ANGLE_PI / 2 {
+            angle = -ANGLE_PI - angle;
+            flip_cos = true;
+        }
+
+        let mut angle = angle as i64;
+        let (mut x, mut y) = (CORDIC_GAIN, 0_i64);
+        for i in 0..CORDIC_ITERATIONS {
+            let step = ATAN
+                .get(i)
+                .cloned()
+                .unwrap_or(1 << (ANGLE_BITS as usize - i));
+            let (dx, dy) = (y >> i, x >> i);
+            if angle >= 0 {
+                x -= dx;
+                y += dy;
+                angle -= step;
+            } else {
+                x += dx;
+                y -= dy;
+                angle += step;
+            }
+        }
+
+        let to_fixed = |v: i64| Fixed((v + (1 << (extra_bits - 1))) >> extra_bits);
+        let cos = if flip_cos { -x } else { x };
+        (to_fixed(y), to_fixed(cos))
+    }
+}
+
+impl fmt::Debug for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.to_f64())
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_f64(), f)
+    }
+}
+
+// Files store the value as a float, so they are the same whatever the number type
+impl Serialize for Fixed {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer).map(Fixed::from_f64)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        saturate((i128::from(self.0) * i128::from(rhs.0)) >> FRACTION_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            return if self.0 >= 0 { Fixed::MAX } else { Fixed::MIN };
+        }
+        saturate((i128::from(self.0) << FRACTION_BITS) / i128::from(rhs.0))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs;
+    }
+}
+
+impl Sum for Fixed {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Fixed(0), |a, b| a + b)
+    }
+}
+
+impl<'a> Sum<&'a Fixed> for Fixed {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.fold(Fixed(0), |a, &b| a + b)
+    }
+}
+
+impl Scalar for Fixed {
+    const ZERO: Self = Fixed(0);
+    const INFINITY: Self = Fixed::MAX;
+    const NEG_INFINITY: Self = Fixed::MIN;
+    fn from_f64(x: f64) -> Self {
+        // `as` saturates, and maps the infinities to the extreme values
+        Fixed((x * ONE as f64).round() as i64)
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / ONE as f64
+    }
+    fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed(0);
+        }
+        // Integer square root of the value scaled by another 2^32, bit by bit
+        let n = (self.0 as u128) << FRACTION_BITS;
+        let mut result: u128 = 0;
+        let mut bit: u128 = 1 << 94;
+        while bit > n {
+            bit >>= 2;
+        }
+        let mut rest = n;
+        while bit != 0 {
+            if rest >= result + bit {
+                rest -= result + bit;
+                result = (result >> 1) + bit;
+            } else {
+                result >>= 1;
+            }
+            bit >>= 2;
+        }
+        Fixed(result as i64)
+    }
+    fn sin(self) -> Self {
+        Fixed::sin_cos(self).0
+    }
+    fn cos(self) -> Self {
+        Fixed::sin_cos(self).1
+    }
+    fn sin_cos(self) -> (Self, Self) {
+        Fixed::sin_cos(self)
+    }
+    fn abs(self) -> Self {
+        Fixed(self.0.saturating_abs())
+    }
+    fn min(self, other: Self) -> Self {
+        Fixed(self.0.min(other.0))
+    }
+    fn max(self, other: Self) -> Self {
+        Fixed(self.0.max(other.0))
+    }
+    fn clamp(self, min: Self, max: Self) -> Self {
+        Fixed(self.0.clamp(min.0, max.0))
+    }
+    fn round(self) -> Self {
+        let half = ONE / 2;
+        Fixed(self.0.saturating_add(half) & !(ONE - 1))
+    }
+    fn is_finite(self) -> bool {
+        self != Fixed::MAX && self != Fixed::MIN
+    }
+    fn is_infinite(self) -> bool {
+        !self.is_finite()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    #[test]
+    fn sin_cos_matches_f64() {
+        // Every quadrant, the folding points and angles far from zero
+        let mut angles: Vec<f64> = (-400..=400).map(|i| i as f64 * 0.0625).collect();
+        angles.extend(&[PI / 2.0, -PI / 2.0, PI, -PI, 2.0 * PI, 1000.0, -12345.678]);
+        for &angle in &angles {
+            let (s, c) = Fixed::from_f64(angle).sin_cos();
+            // The angle itself is rounded to 2^-32 first
+            let angle = Fixed::from_f64(angle).to_f64();
+            assert!((s.to_f64() - angle.sin()).abs() < 1e-8, "sin {}", angle);
+            assert!((c.to_f64() - angle.cos()).abs() < 1e-8, "cos {}", angle);
+        }
+    }
+
+    #[test]
+    fn stepping_a_scene_does_not_saturate() {
+        let mut scene = Scene::with_seed(3);
+        for i in 0..5 {
+            let x = 5.0 + 2.5 * i as f64;
+            scene.add_polygon(x, 3.0);
+            scene.add_circle(x + 0.7, 6.0);
+            scene.add_capsule(x - 0.7, 9.0);
+        }
+        for _ in 0..600 {
+            scene.step();
+        }
+        for body in scene.bodies() {
+            let object = body.object();
+            let object = object.borrow();
+            if object.inverse_mass == Fixed::ZERO {
+                continue;
+            }
+            for &value in &[
+                object.position.x,
+                object.position.y,
+                object.velocity.x,
+                object.velocity.y,
+                object.orient,
+                object.angular_velocity,
+            ] {
+                assert!(value.is_finite());
+                assert!(value.abs() < Fixed::from_f64(1.0e6));
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::math::*;
+use std::error::Error;
+use std::fmt;
+
+// Tolerance for areas and cross products, so nearly collinear points count as collinear
+const EPSILON: f64 = 1.0e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolygonError {
+    TooFewVertices,
+    InvalidDimensions,
+    DegenerateArea,
+    DegenerateEdge,
+    NonConvex,
+    SelfIntersecting,
+    WrongWinding,
+}
+
+impl fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            PolygonError::TooFewVertices => "a polygon needs at least 3 vertices",
+            PolygonError::InvalidDimensions => "polygon dimensions must be positive",
+            PolygonError::DegenerateArea => "polygon has no area",
+            PolygonError::DegenerateEdge => "polygon has a zero-length edge or collinear vertices",
+            PolygonError::NonConvex => "polygon is not convex",
+            PolygonError::SelfIntersecting => "polygon edges intersect each other",
+            PolygonError::WrongWinding => "polygon vertices are in clockwise order",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for PolygonError {}
+
+// Twice the signed area, positive for counter-clockwise vertices (y pointing up)
+pub fn signed_area(vertices: &[Vector2d<Real>]) -> Real {
+    let n = vertices.len();
+    let mut area = real(0.0);
+    for i1 in 0..n {
+        let i2 = if i1 + 1 < n { i1 + 1 } else { 0 };
+        area += vertices[i1].cross_product(vertices[i2]);
+    }
+    area
+}
+
+fn is_convex_corner(
+    previous: Vector2d<Real>,
+    current: Vector2d<Real>,
+    next: Vector2d<Real>,
+) -> bool {
+    (current - previous).cross_product(next - current) > real(0.0)
+}
+
+fn is_inside_triangle(
+    p: Vector2d<Real>,
+    a: Vector2d<Real>,
+    b: Vector2d<Real>,
+    c: Vector2d<Real>,
+) -> bool {
+    (b - a).cross_product(p - a) >= real(0.0)
+        && (c - b).cross_product(p - b) >= real(0.0)
+        && (a - c).cross_product(p - c) >= real(0.0)
+}
+
+// Whether two consecutive vertices are the same point
+pub fn has_zero_length_edge(vertices: &[Vector2d<Real>]) -> bool {
+    let n = vertices.len();
+    (0..n).any(|i| (vertices[(i + 1) % n] - vertices[i]).len_square() <= real(EPSILON * EPSILON))
+}
+
+// Check that the vertices form a convex polygon that bodies can be built from. Vertices must be
+// counter-clockwise with y pointing up, which is clockwise on the canvas.
+pub fn validate_convex(vertices: &[Vector2d<Real>]) -> Result<(), PolygonError> {
+    let n = vertices.len();
+    if n < 3 {
+        return Err(PolygonError::TooFewVertices);
+    }
+    if has_zero_length_edge(vertices) {
+        return Err(PolygonError::DegenerateEdge);
+    }
+    let area = signed_area(vertices);
+    if area.abs() <= real(EPSILON) {
+        return Err(PolygonError::DegenerateArea);
+    }
+    // Collinear vertices would give two edges the same normal
+    for i1 in 0..n {
+        let i2 = if i1 + 1 < n { i1 + 1 } else { 0 };
+        let i3 = if i2 + 1 < n { i2 + 1 } else { 0 };
+        let e1 = vertices[i2] - vertices[i1];
+        let e2 = vertices[i3] - vertices[i2];
+        if e1.cross_product(e2).abs() <= real(EPSILON) {
+            return Err(PolygonError::DegenerateEdge);
+        }
+    }
+    if !is_simple(vertices) {
+        return Err(PolygonError::SelfIntersecting);
+    }
+    if area < real(0.0) {
+        return Err(PolygonError::WrongWinding);
+    }
+    for i1 in 0..n {
+        let i2 = if i1 + 1 < n { i1 + 1 } else { 0 };
+        let i3 = if i2 + 1 < n { i2 + 1 } else { 0 };
+        let e1 = vertices[i2] - vertices[i1];
+        let e2 = vertices[i3] - vertices[i2];
+        if e1.cross_product(e2) < real(-EPSILON) {
+            return Err(PolygonError::NonConvex);
+        }
+    }
+    Ok(())
+}
+
+// Convex hull by Andrew's monotone chain, counter-clockwise with y pointing up.
+// Duplicate and collinear points are dropped, so the result may have less than 3 vertices.
+pub fn convex_hull(points: &[Vector2d<Real>]) -> Vec<Vector2d<Real>> {
+    let mut points: Vec<_> = points
+        .iter()
+        .cloned()
+        .filter(|p| p.x.is_finite() && p.y.is_finite())
+        .collect();
+    points.sort_by(|a, b| {
+        a.x.partial_cmp(&b.x)
+            .unwrap()
+            .then(a.y.partial_cmp(&b.y).unwrap())
+    });
+    points.dedup_by(|a, b| (*a - *b).len_square() <= real(EPSILON * EPSILON));
+    if points.len() < 3 {
+        return points;
+    }
+
+    // Build the lower hull left to right and the upper hull right to left, dropping
+    // every point that does not make a strict left turn
+    let left_turn = |hull: &[Vector2d<Real>], p: Vector2d<Real>| {
+        let a = hull[hull.len() - 2];
+        let b = hull[hull.len() - 1];
+        (b - a).cross_product(p - b) > real(EPSILON)
+    };
+    let mut lower: Vec<Vector2d<Real>> = Vec::new();
+    for &p in &points {
+        while lower.len() >= 2 && !left_turn(&lower, p) {
+            lower.pop();
+        }
+        lower.push(p);
+    }
+    let mut upper: Vec<Vector2d<Real>> = Vec::new();
+    for &p in points.iter().rev() {
+        while upper.len() >= 2 && !left_turn(&upper, p) {
+            upper.pop();
+        }
+        upper.push(p);
+    }
+
+    // The last point of each half is the first one of the other
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+fn segments_intersect(
+    p1: Vector2d<Real>,
+    q1: Vector2d<Real>,
+    p2: Vector2d<Real>,
+    q2: Vector2d<Real>,
+) -> bool {
+    let d1 = (q2 - p2).cross_product(p1 - p2);
+    let d2 = (q2 - p2).cross_product(q1 - p2);
+    let d3 = (q1 - p1).cross_product(p2 - p1);
+    let d4 = (q1 - p1).cross_product(q2 - p1);
+    if d1 == real(0.0) && d2 == real(0.0) {
+        // Collinear, they intersect only if their projections overlap
+        let axis = q1 - p1;
+        let (min1, max1) = (real(0.0), axis * axis);
+        let (s, t) = (axis * (p2 - p1), axis * (q2 - p1));
+        return s.min(t) <= max1 && s.max(t) >= min1;
+    }
+    d1 * d2 <= real(0.0) && d3 * d4 <= real(0.0)
+}
+
+// Whether no two edges of the polygon touch, except neighbours at their shared vertex
+pub fn is_simple(vertices: &[Vector2d<Real>]) -> bool {
+    let n = vertices.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            // Neighbouring edges always share a vertex
+            if j == i + 1 || (i == 0 && j == n - 1) {
+                continue;
+            }
+            if segments_intersect(
+                vertices[i],
+                vertices[(i + 1) % n],
+                vertices[j],
+                vertices[(j + 1) % n],
+            ) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// Ear clipping triangulation of a simple counter-clockwise polygon. Returns triangles as
+// counter-clockwise vertex indices, or None if the polygon is not simple.
+pub fn triangulate(vertices: &[Vector2d<Real>]) -> Option<Vec<[usize; 3]>> {
+    let mut remaining: Vec<usize> = (0..vertices.len()).collect();
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let mut ear = None;
+        for i in 0..n {
+            let previous = remaining[(i + n - 1) % n];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % n];
+            let (a, b, c) = (vertices[previous], vertices[current], vertices[next]);
+            // Collinear vertices add nothing, drop them
+            if (b - a).cross_product(c - b) == real(0.0) {
+                ear = Some((i, None));
+                break;
+            }
+            if !is_convex_corner(a, b, c) {
+                continue;
+            }
+            let blocked = remaining.iter().any(|&j| {
+                j != previous
+                    && j != current
+                    && j != next
+                    && is_inside_triangle(vertices[j], a, b, c)
+            });
+            if !blocked {
+                ear = Some((i, Some([previous, current, next])));
+                break;
+            }
+        }
+        // Every simple polygon has an ear, so this one crosses itself
+        let (i, triangle) = ear?;
+        triangles.extend(triangle);
+        remaining.remove(i);
+    }
+    if remaining.len() == 3 {
+        let (a, b, c) = (
+            vertices[remaining[0]],
+            vertices[remaining[1]],
+            vertices[remaining[2]],
+        );
+        if (b - a).cross_product(c - b) > real(0.0) {
+            triangles.push([remaining[0], remaining[1], remaining[2]]);
+        }
+    }
+    Some(triangles)
+}
+
+// Merge the polygons sharing the diagonal `from`-`to` if the result is still convex
+fn merge(
+    vertices: &[Vector2d<Real>],
+    a: &[usize],
+    b: &[usize],
+    from: usize,
+    to: usize,
+) -> Option<Vec<usize>> {
+    // `a` runs from -> to along the diagonal, `b` runs to -> from
+    let start_a = a.iter().position(|&v| v == to)?;
+    let start_b = b.iter().position(|&v| v == from)?;
+    let mut merged: Vec<usize> = (0..a.len()).map(|k| a[(start_a + k) % a.len()]).collect();
+    merged.extend((1..b.len() - 1).map(|k| b[(start_b + k) % b.len()]));
+
+    let n = merged.len();
+    let convex = (0..n).all(|i| {
+        let previous = vertices[merged[(i + n - 1) % n]];
+        let next = vertices[merged[(i + 1) % n]];
+        (vertices[merged[i]] - previous).cross_product(next - vertices[merged[i]]) >= real(0.0)
+    });
+    if convex {
+        Some(merged)
+    } else {
+        None
+    }
+}
+
+// Split a simple polygon into convex pieces: triangulate it, then remove every diagonal
+// whose removal keeps the merged piece convex (Hertel-Mehlhorn).
+// Accepts either winding and returns counter-clockwise pieces.
+pub fn decompose(vertices: &[Vector2d<Real>]) -> Result<Vec<Vec<Vector2d<Real>>>, PolygonError> {
+    if vertices.len() < 3 {
+        return Err(PolygonError::TooFewVertices);
+    }
+    if has_zero_length_edge(vertices) {
+        return Err(PolygonError::DegenerateEdge);
+    }
+    if signed_area(vertices).abs() <= real(EPSILON) {
+        return Err(PolygonError::DegenerateArea);
+    }
+    if !is_simple(vertices) {
+        return Err(PolygonError::SelfIntersecting);
+    }
+    let mut vertices = vertices.to_vec();
+    if signed_area(&vertices) < real(0.0) {
+        vertices.reverse();
+    }
+    let mut pieces: Vec<Vec<usize>> = triangulate(&vertices)
+        .ok_or(PolygonError::SelfIntersecting)?
+        .iter()
+        .map(|t| t.to_vec())
+        .collect();
+
+    let mut merged_any = true;
+    while merged_any {
+        merged_any = false;
+        'search: for i in 0..pieces.len() {
+            for j in i + 1..pieces.len() {
+                let n = pieces[i].len();
+                for k in 0..n {
+                    let from = pieces[i][k];
+                    let to = pieces[i][(k + 1) % n];
+                    // A shared diagonal runs the other way in the neighbouring piece
+                    let m = pieces[j].len();
+                    let shared =
+                        (0..m).any(|l| pieces[j][l] == to && pieces[j][(l + 1) % m] == from);
+                    if !shared {
+                        continue;
+                    }
+                    if let Some(piece) = merge(&vertices, &pieces[i], &pieces[j], from, to) {
+                        pieces[i] = piece;
+                        pieces.remove(j);
+                        merged_any = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(pieces
+        .iter()
+        .map(|piece| piece.iter().map(|&i| vertices[i]).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(points: &[(f64, f64)]) -> Vec<Vector2d<Real>> {
+        points
+            .iter()
+            .map(|&(x, y)| Vector2d::new(x, y).to_real())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_counter_clockwise_convex_polygon() {
+        let square = outline(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(validate_convex(&square), Ok(()));
+    }
+
+    #[test]
+    fn rejects_duplicate_vertex() {
+        let vertices = outline(&[
+            (0.0, -1.0),
+            (0.0, -1.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+            (-1.0, 0.0),
+        ]);
+        assert_eq!(
+            validate_convex(&vertices),
+            Err(PolygonError::DegenerateEdge)
+        );
+        assert_eq!(decompose(&vertices), Err(PolygonError::DegenerateEdge));
+    }
+
+    #[test]
+    fn rejects_collinear_vertices() {
+        let vertices = outline(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        assert_eq!(
+            validate_convex(&vertices),
+            Err(PolygonError::DegenerateEdge)
+        );
+    }
+
+    #[test]
+    fn rejects_clockwise_winding() {
+        let vertices = outline(&[(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]);
+        assert_eq!(validate_convex(&vertices), Err(PolygonError::WrongWinding));
+    }
+
+    #[test]
+    fn rejects_concave_polygon() {
+        let arrow = outline(&[(0.0, 0.0), (2.0, 1.0), (0.0, 2.0), (1.0, 1.0)]);
+        assert_eq!(validate_convex(&arrow), Err(PolygonError::NonConvex));
+    }
+
+    #[test]
+    fn rejects_self_intersecting_polygon() {
+        let bowtie = outline(&[(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (3.0, 3.0)]);
+        assert_eq!(
+            validate_convex(&bowtie),
+            Err(PolygonError::SelfIntersecting)
+        );
+        assert_eq!(decompose(&bowtie), Err(PolygonError::SelfIntersecting));
+    }
+
+    #[test]
+    fn rejects_too_few_vertices_and_no_area() {
+        let line = outline(&[(0.0, 0.0), (1.0, 0.0)]);
+        assert_eq!(validate_convex(&line), Err(PolygonError::TooFewVertices));
+        let flat = outline(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0)]);
+        assert_eq!(validate_convex(&flat), Err(PolygonError::DegenerateArea));
+    }
+
+    #[test]
+    fn hull_drops_interior_duplicate_and_collinear_points() {
+        let points = outline(&[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (1.0, 1.0),
+            (0.0, 2.0),
+            (2.0, 2.0),
+        ]);
+        let hull = convex_hull(&points);
+        assert_eq!(
+            hull,
+            outline(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)])
+        );
+        assert_eq!(validate_convex(&hull), Ok(()));
+    }
+
+    #[test]
+    fn decomposes_l_shape_into_two_convex_pieces() {
+        let l_shape = outline(&[
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+        ]);
+        for vertices in [l_shape.clone(), l_shape.iter().rev().cloned().collect()] {
+            let pieces = decompose(&vertices).unwrap();
+            assert_eq!(pieces.len(), 2);
+            let mut area = real(0.0);
+            for piece in &pieces {
+                assert_eq!(validate_convex(piece), Ok(()));
+                area += signed_area(piece);
+            }
+            assert_eq!(area, signed_area(&l_shape));
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use crate::body::*;
+use crate::material::*;
+use crate::math::*;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Bumped whenever a file written by an older version would load differently
+pub const LEVEL_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Ron(ron::error::SpannedError),
+    Svg(roxmltree::Error),
+    Serialize(String),
+    UnknownFormat(String),
+    MissingVersion,
+    UnsupportedVersion(u32),
+    InvalidSetting(&'static str),
+    InvalidBody(usize, String),
+    JointsNotSupported,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(e) => write!(f, "cannot access the level file: {}", e),
+            LevelError::Json(e) => write!(f, "invalid JSON level: {}", e),
+            LevelError::Ron(e) => write!(f, "invalid RON level: {}", e),
+            LevelError::Svg(e) => write!(f, "invalid SVG: {}", e),
+            LevelError::Serialize(e) => write!(f, "cannot write the level: {}", e),
+            LevelError::UnknownFormat(extension) => write!(
+                f,
+                "unknown level format \"{}\", use .json or .ron",
+                extension
+            ),
+            LevelError::MissingVersion => write!(f, "the level has no version"),
+            LevelError::UnsupportedVersion(version) => write!(
+                f,
+                "level version {} is newer than the supported version {}",
+                version, LEVEL_VERSION
+            ),
+            LevelError::InvalidSetting(message) => write!(f, "{}", message),
+            LevelError::InvalidBody(index, message) => write!(f, "body {}: {}", index, message),
+            LevelError::JointsNotSupported => write!(f, "joints are not supported"),
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(e: io::Error) -> Self {
+        LevelError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LevelError {
+    fn from(e: serde_json::Error) -> Self {
+        LevelError::Json(e)
+    }
+}
+
+impl From<roxmltree::Error> for LevelError {
+    fn from(e: roxmltree::Error) -> Self {
+        LevelError::Svg(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for LevelError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        LevelError::Ron(e)
+    }
+}
+
+// Shapes in the local space of their body. Polygons, compounds and chains are moved so that
+// their center is at the body's position, like their constructors do.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Shape {
+    Circle {
+        radius: Real,
+    },
+    Capsule {
+        half_length: Real,
+        radius: Real,
+    },
+    Box {
+        half_width: Real,
+        half_height: Real,
+    },
+    // Convex, counter-clockwise with y pointing up
+    Polygon {
+        vertices: Vec<Vector2d<Real>>,
+    },
+    // Any simple outline, split into convex pieces, or convex pieces given one by one which may
+    // overlap. Only one of the two is given.
+    Compound {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        vertices: Vec<Vector2d<Real>>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pieces: Vec<Vec<Vector2d<Real>>>,
+    },
+    Edge {
+        v0: Option<Vector2d<Real>>,
+        v1: Vector2d<Real>,
+        v2: Vector2d<Real>,
+        v3: Option<Vector2d<Real>>,
+    },
+    Chain {
+        vertices: Vec<Vector2d<Real>>,
+        is_loop: bool,
+    },
+}
+
+// A body as stored in a level, every field but `shape` may be left out
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BodyDef {
+    pub shape: Shape,
+    pub position: Vector2d<Real>,
+    pub orient: Real,
+    pub velocity: Vector2d<Real>,
+    pub angular_velocity: Real,
+    pub is_static: bool,
+    pub density: Real,
+    // Replaces the mass computed from the shape and density, `center` is relative to `position`
+    pub mass_data: Option<MassData>,
+    pub fixed_rotation: bool,
+    pub material: Material,
+    pub tangent_speed: Real,
+    pub anisotropic_friction: Vector2d<Real>,
+    pub filter: Filter,
+    pub is_sensor: bool,
+    pub one_way: bool,
+    // Picked by the scene's random generator when left out
+    pub color: Option<Color>,
+}
+
+impl Default for BodyDef {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Circle { radius: real(1.0) },
+            position: Vector2d::zero(),
+            orient: real(0.0),
+            velocity: Vector2d::zero(),
+            angular_velocity: real(0.0),
+            is_static: false,
+            density: real(1.0),
+            mass_data: None,
+            fixed_rotation: false,
+            material: Material::default(),
+            tangent_speed: real(0.0),
+            anisotropic_friction: Vector2d::new(real(1.0), real(1.0)),
+            filter: Filter::default(),
+            is_sensor: false,
+            one_way: false,
+            color: None,
+        }
+    }
+}
+
+fn positive(value: Real, name: &str) -> Result<(), String> {
+    if value > real(0.0) && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} must be positive", name))
+    }
+}
+
+impl BodyDef {
+    // Describe a body of the scene, `None` for body types that levels do not know
+    pub fn from_body(body: &dyn RigidBody) -> Option<Self> {
+        let shape = if let Some(circle) = body.downcast_ref::<Circle>() {
+            Shape::Circle {
+                radius: circle.radius,
+            }
+        } else if let Some(capsule) = body.downcast_ref::<Capsule>() {
+            Shape::Capsule {
+                half_length: capsule.half_length,
+                radius: capsule.radius,
+            }
+        } else if let Some(polygon) = body.downcast_ref::<Polygon>() {
+            Shape::Polygon {
+                vertices: polygon.vertices.clone(),
+            }
+        } else if let Some(compound) = body.downcast_ref::<Compound>() {
+            Shape::Compound {
+                vertices: compound.outline.clone(),
+                pieces: if compound.outline.is_empty() {
+                    compound.pieces.iter().map(|p| p.vertices.clone()).collect()
+                } else {
+                    Vec::new()
+                },
+            }
+        } else if let Some(edge) = body.downcast_ref::<Edge>() {
+            Shape::Edge {
+                v0: edge.v0,
+                v1: edge.v1,
+                v2: edge.v2,
+                v3: edge.v3,
+            }
+        } else if let Some(chain) = body.downcast_ref::<Chain>() {
+            Shape::Chain {
+                vertices: chain.vertices.clone(),
+                is_loop: chain.is_loop,
+            }
+        } else {
+            return None;
+        };
+        let object = body.object();
+        let object = object.borrow();
+        Some(Self {
+            shape,
+            position: object.position,
+            orient: object.orient,
+            velocity: object.velocity,
+            angular_velocity: object.angular_velocity,
+            is_static: object.inverse_mass == real(0.0),
+            density: object.density,
+            mass_data: object.mass_data,
+            fixed_rotation: object.fixed_rotation,
+            material: object.material.clone(),
+            tangent_speed: object.tangent_speed,
+            anisotropic_friction: object.anisotropic_friction,
+            filter: object.filter,
+            is_sensor: object.is_sensor,
+            one_way: object.one_way,
+            color: Some(object.color.clone()),
+        })
+    }
+
+    pub fn build(&self) -> Result<Box<dyn RigidBody>, String> {
+        let p = self.position;
+        let mut body: Box<dyn RigidBody> = match &self.shape {
+            Shape::Circle { radius } => {
+                positive(*radius, "radius")?;
+                Box::new(Circle::new(p.x, p.y, *radius))
+            }
+            Shape::Capsule {
+                half_length,
+                radius,
+            } => {
+                positive(*half_length, "half_length")?;
+                positive(*radius, "radius")?;
+                Box::new(Capsule::new(p.x, p.y, *half_length, *radius))
+            }
+            Shape::Box {
+                half_width,
+                half_height,
+            } => {
+                positive(*half_width, "half_width")?;
+                positive(*half_height, "half_height")?;
+                Box::new(
+                    Polygon::new_box(p, *half_width, *half_height, real(0.0))
+                        .map_err(|e| e.to_string())?,
+                )
+            }
+            Shape::Polygon { vertices } => {
+                Box::new(Polygon::from_vertices(p, vertices).map_err(|e| e.to_string())?)
+            }
+            Shape::Compound { vertices, pieces } => {
+                let compound = match (vertices.is_empty(), pieces.is_empty()) {
+                    (false, false) => {
+                        return Err("a compound has either vertices or pieces".to_string())
+                    }
+                    (_, true) => Compound::from_vertices(p, vertices),
+                    (true, false) => Compound::from_pieces(p, pieces),
+                };
+                Box::new(compound.map_err(|e| e.to_string())?)
+            }
+            Shape::Edge { v0, v1, v2, v3 } => {
+                let mut edge = Edge::new(*v1 + p, *v2 + p).map_err(|e| e.to_string())?;
+                edge.set_ghost_vertices(v0.map(|v| v + p), v3.map(|v| v + p));
+                Box::new(edge)
+            }
+            Shape::Chain { vertices, is_loop } => {
+                let count = if *is_loop { 3 } else { 2 };
+                if vertices.len() < count {
+                    return Err(format!("a chain needs at least {} vertices", count));
+                }
+                let vertices: Vec<_> = vertices.iter().map(|&v| v + p).collect();
+                let chain = if *is_loop {
+                    Chain::new_loop(&vertices)
+                } else {
+                    Chain::new(&vertices)
+                };
+                Box::new(chain.map_err(|e| e.to_string())?)
+            }
+        };
+
+        positive(self.density, "density")?;
+        body.set_density(self.density);
+        let object = body.object();
+        let mut object = object.borrow_mut();
+        if let Some(data) = self.mass_data {
+            // The constructors may have moved the body to the center of its shape
+            let center = data.center - (object.position - p);
+            object
+                .set_mass_data(data.mass, center, data.inertia)
+                .map_err(|e| e.to_string())?;
+        }
+        if self.is_static {
+            object.set_static();
+        }
+        object.set_fixed_rotation(self.fixed_rotation);
+        object.orient = self.orient;
+        object.velocity = self.velocity;
+        object.angular_velocity = self.angular_velocity;
+        object.material = self.material.clone();
+        object.tangent_speed = self.tangent_speed;
+        object.anisotropic_friction = self.anisotropic_friction;
+        object.filter = self.filter;
+        object.is_sensor = self.is_sensor;
+        object.one_way = self.one_way;
+        if let Some(ref color) = self.color {
+            object.color = color.clone();
+        }
+        drop(object);
+        Ok(body)
+    }
+}
+
+// A whole scene as stored in a file. Everything but `version` may be left out.
+// The engine has no joints, so neither do levels, and files with a `joints` entry are rejected.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Level {
+    pub version: u32,
+    pub gravity: Vector2d<Real>,
+    pub timestep: Real,
+    pub iterations: u32,
+    pub position_iterations: u32,
+    pub linear_slop: Real,
+    pub correction_rate: Real,
+    pub restitution_threshold: Real,
+    // Seeded from the operating system when left out
+    pub seed: Option<u64>,
+    // Where the random numbers of `seed` continue from, they start over when left out
+    pub rng_state: Option<u64>,
+    pub materials: MaterialTable,
+    pub bodies: Vec<BodyDef>,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            version: LEVEL_VERSION,
+            gravity: Vector2d::new(real(0.0), real(9.8)),
+            timestep: real(1.0 / 60.0),
+            iterations: 10,
+            position_iterations: 4,
+            linear_slop: real(0.005),
+            correction_rate: real(0.4),
+            restitution_threshold: real(1.0),
+            seed: None,
+            rng_state: None,
+            materials: MaterialTable::new(),
+            bodies: Vec::new(),
+        }
+    }
+}
+
+// Read first, so that files from a newer version fail with a clear error instead of whatever
+// field the new version changed
+#[derive(Deserialize)]
+#[serde(rename = "Level")]
+struct Header {
+    // Versions start at 1
+    #[serde(default)]
+    version: u32,
+    // Only looked for, so that levels written for an engine with joints fail clearly
+    #[serde(default)]
+    joints: Option<IgnoredAny>,
+}
+
+fn check_version(header: Header) -> Result<(), LevelError> {
+    match header.version {
+        0 => return Err(LevelError::MissingVersion),
+        version if version > LEVEL_VERSION => return Err(LevelError::UnsupportedVersion(version)),
+        _ => {}
+    }
+    if header.joints.is_some() {
+        return Err(LevelError::JointsNotSupported);
+    }
+    Ok(())
+}
+
+// Optional values like `color` can be written without `Some(...)`
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+}
+
+impl Level {
+    pub fn from_json(text: &str) -> Result<Self, LevelError> {
+        check_version(serde_json::from_str(text)?)?;
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, LevelError> {
+        check_version(ron_options().from_str(text)?)?;
+        Ok(ron_options().from_str(text)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, LevelError> {
+        serde_json::to_string_pretty(self).map_err(|e| LevelError::Serialize(e.to_string()))
+    }
+
+    pub fn to_ron(&self) -> Result<String, LevelError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| LevelError::Serialize(e.to_string()))
+    }
+
+    // The format is picked from the extension, .json or .ron
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LevelError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match extension(path).as_str() {
+            "json" => Self::from_json(&text),
+            "ron" => Self::from_ron(&text),
+            other => Err(LevelError::UnknownFormat(other.to_string())),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), LevelError> {
+        let path = path.as_ref();
+        let text = match extension(path).as_str() {
+            "json" => self.to_json()?,
+            "ron" => self.to_ron()?,
+            other => return Err(LevelError::UnknownFormat(other.to_string())),
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    // Settings that would make the simulation blow up or stall
+    pub fn validate(&self) -> Result<(), LevelError> {
+        if !(self.timestep > real(0.0) && self.timestep.is_finite()) {
+            return Err(LevelError::InvalidSetting("timestep must be positive"));
+        }
+        if !(self.gravity.x.is_finite() && self.gravity.y.is_finite()) {
+            return Err(LevelError::InvalidSetting("gravity must be finite"));
+        }
+        if self.linear_slop < real(0.0) {
+            return Err(LevelError::InvalidSetting(
+                "linear_slop must not be negative",
+            ));
+        }
+        if !(self.correction_rate >= real(0.0) && self.correction_rate <= real(1.0)) {
+            return Err(LevelError::InvalidSetting(
+                "correction_rate must be between 0 and 1",
+            ));
+        }
+        if self.rng_state.is_some() && self.seed.is_none() {
+            return Err(LevelError::InvalidSetting("rng_state needs a seed"));
+        }
+        for (i, body) in self.bodies.iter().enumerate() {
+            if let Shape::Edge { v1, v2, .. } = body.shape {
+                if v1 == v2 {
+                    return Err(LevelError::InvalidBody(
+                        i,
+                        "the edge has no length".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    fn sample() -> Level {
+        let mut level = Level {
+            seed: Some(42),
+            ..Level::default()
+        };
+        level.bodies.push(BodyDef {
+            shape: Shape::Box {
+                half_width: real(5.0),
+                half_height: real(0.5),
+            },
+            position: Vector2d::new(10.0, 15.0).to_real(),
+            is_static: true,
+            ..BodyDef::default()
+        });
+        level.bodies.push(BodyDef {
+            shape: Shape::Capsule {
+                half_length: real(0.5),
+                radius: real(0.25),
+            },
+            position: Vector2d::new(9.0, 5.0).to_real(),
+            orient: real(0.3),
+            mass_data: Some(MassData {
+                mass: real(2.0),
+                center: Vector2d::new(0.25, -0.125).to_real(),
+                inertia: real(0.5),
+            }),
+            color: Some(Color::new(10, 20, 30)),
+            ..BodyDef::default()
+        });
+        level
+    }
+
+    #[test]
+    fn json_and_ron_round_trip() {
+        let level = sample();
+        let json = level.to_json().unwrap();
+        assert_eq!(Level::from_json(&json).unwrap().to_json().unwrap(), json);
+        let ron = level.to_ron().unwrap();
+        assert_eq!(Level::from_ron(&ron).unwrap().to_ron().unwrap(), ron);
+        assert_eq!(Level::from_ron(&ron).unwrap().to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn scene_round_trip_keeps_mass_data() {
+        let scene = Scene::from_level(&sample()).unwrap();
+        let text = scene.to_level().to_json().unwrap();
+        let loaded = Scene::from_level(&Level::from_json(&text).unwrap()).unwrap();
+        for (a, b) in scene.bodies().iter().zip(loaded.bodies()) {
+            let (a, b) = (a.object(), b.object());
+            let (a, b) = (a.borrow(), b.borrow());
+            assert_eq!(a.mass_data, b.mass_data);
+            assert_eq!(a.mass, b.mass);
+            assert_eq!(a.inertia, b.inertia);
+            assert_eq!(a.center, b.center);
+            assert_eq!(a.position, b.position);
+        }
+        let capsule = scene.bodies()[1].object();
+        let capsule = capsule.borrow();
+        assert_eq!(capsule.mass, real(2.0));
+        assert_eq!(capsule.position, Vector2d::new(9.0, 5.0).to_real());
+    }
+
+    #[test]
+    fn version_is_checked_first() {
+        assert!(matches!(
+            Level::from_json(r#"{ "bodies": [] }"#),
+            Err(LevelError::MissingVersion)
+        ));
+        assert!(matches!(
+            Level::from_json(r#"{ "version": 99, "future_field": 1 }"#),
+            Err(LevelError::UnsupportedVersion(99))
+        ));
+        assert!(matches!(
+            Level::from_ron("(version: 99)"),
+            Err(LevelError::UnsupportedVersion(99))
+        ));
+        assert!(matches!(
+            Level::from_json(r#"{ "version": 1, "typo": 1 }"#),
+            Err(LevelError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn joints_are_rejected() {
+        assert!(matches!(
+            Level::from_json(r#"{ "version": 1, "joints": [{ "type": "revolute" }] }"#),
+            Err(LevelError::JointsNotSupported)
+        ));
+        assert!(matches!(
+            Level::from_ron("(version: 1, joints: [])"),
+            Err(LevelError::JointsNotSupported)
+        ));
+    }
+
+    #[test]
+    fn invalid_mass_data_is_a_body_error() {
+        let mut level = sample();
+        level.bodies[1].mass_data.as_mut().unwrap().mass = real(0.0);
+        assert!(matches!(
+            Scene::from_level(&level),
+            Err(LevelError::InvalidBody(1, _))
+        ));
+    }
+
+    #[test]
+    fn scene_round_trip_continues_the_random_numbers() {
+        let mut scene = Scene::from_level(&sample()).unwrap();
+        scene.add_circle(5.0, 5.0);
+        let level = Level::from_json(&scene.to_level().to_json().unwrap()).unwrap();
+        assert_eq!(level.seed, Some(42));
+        let mut loaded = Scene::from_level(&level).unwrap();
+        // The next random shapes are the same as if the scene had gone on
+        scene.add_polygon(5.0, 5.0);
+        loaded.add_polygon(5.0, 5.0);
+        let a = scene.bodies().last().unwrap().object();
+        let b = loaded.bodies().last().unwrap().object();
+        let (a, b) = (a.borrow(), b.borrow());
+        assert_eq!(a.mass, b.mass);
+        assert_eq!(a.orient, b.orient);
+        assert_eq!(
+            (a.color.r, a.color.g, a.color.b),
+            (b.color.r, b.color.g, b.color.b)
+        );
+    }
+
+    #[test]
+    fn invalid_levels_fail_validation() {
+        let level = Level {
+            rng_state: Some(1),
+            ..Level::default()
+        };
+        assert!(matches!(
+            level.validate(),
+            Err(LevelError::InvalidSetting(_))
+        ));
+
+        let mut level = sample();
+        let p = Vector2d::new(1.0, 2.0).to_real();
+        level.bodies.push(BodyDef {
+            shape: Shape::Edge {
+                v0: None,
+                v1: p,
+                v2: p,
+                v3: None,
+            },
+            is_static: true,
+            ..BodyDef::default()
+        });
+        assert!(matches!(
+            level.validate(),
+            Err(LevelError::InvalidBody(2, _))
+        ));
+    }
+}
@@ -0,0 +1,14 @@
+pub mod body;
+#[cfg(feature = "fixed")]
+pub mod fixed;
+pub mod geometry;
+pub mod level;
+pub mod manifold;
+pub mod material;
+pub mod math;
+pub mod rand;
+pub mod raster;
+pub mod render;
+pub mod rube;
+pub mod scene;
+pub mod svg;
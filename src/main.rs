@@ -1,16 +1,18 @@
+#[cfg(target_arch = "wasm32")]
 use std::cell::RefCell;
+#[cfg(target_arch = "wasm32")]
 use std::rc::Rc;
+#[cfg(target_arch = "wasm32")]
 use stdweb::traits::*;
-use stdweb::web::event::{ClickEvent, ContextMenuEvent, MouseButton, ResizeEvent};
+#[cfg(target_arch = "wasm32")]
+use stdweb::web::event::{AuxClickEvent, ClickEvent, ContextMenuEvent, MouseButton, ResizeEvent};
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::{window, IEventTarget};
 
-mod body;
-mod manifold;
-mod math;
-mod rand;
-mod scene;
-use self::scene::*;
+#[cfg(target_arch = "wasm32")]
+use impulse_engine_wasm::scene::*;
 
+#[cfg(target_arch = "wasm32")]
 fn game_loop(scene: Rc<RefCell<Scene>>) {
     let window = stdweb::web::window();
     window.request_animation_frame(move |_| {
@@ -21,6 +23,7 @@ fn game_loop(scene: Rc<RefCell<Scene>>) {
     });
 }
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
     stdweb::initialize();
     // Here we need `scene` to be mutable shared
@@ -51,6 +54,17 @@ fn main() {
             _ => {}
         }
     });
+    scene.borrow().canvas().add_event_listener({
+        let scene = scene.clone();
+        move |event: AuxClickEvent| {
+            if event.button() == MouseButton::Wheel {
+                event.prevent_default();
+                scene
+                    .borrow_mut()
+                    .add_capsule(event.offset_x(), event.offset_y());
+            }
+        }
+    });
     stdweb::web::window().add_event_listener({
         let scene = scene.clone();
         move |_: ResizeEvent| {
@@ -66,3 +80,8 @@ fn main() {
 
     stdweb::event_loop();
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("The demo runs in the browser, see the README. Use the `runner` binary to run scene files natively.");
+}
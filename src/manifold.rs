@@ -1,77 +1,358 @@
 use crate::body::*;
+use crate::material::*;
 use crate::math::*;
-use crate::scene::*;
+use crate::render::*;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
 struct Face {
-    v1: Vector2d<f64>,
-    v2: Vector2d<f64>,
-    normal: Vector2d<f64>,
+    v1: Vector2d<Real>,
+    v2: Vector2d<Real>,
+    normal: Vector2d<Real>,
 }
 
+#[derive(Clone)]
 pub struct Manifold {
     object_a: Rc<RefCell<Object>>,
     object_b: Rc<RefCell<Object>>,
-    penetration: f64,
-    pub normal: Vector2d<f64>,
-    pub contacts: Vec<Vector2d<f64>>,
-    mixed_restitution: f64,
-    mixed_dynamic_friction: f64,
-    mixed_static_friction: f64,
+    penetration: Real,
+    pub normal: Vector2d<Real>,
+    pub contacts: Vec<Vector2d<Real>>,
+    mixed_restitution: Real,
+    mixed_dynamic_friction: Real,
+    mixed_static_friction: Real,
+    mixed_rolling_resistance: Real,
+    // Lever arm of the rolling resistance, the radius of the rounder shape
+    rolling_radius: Real,
+    tangent_speed: Real,
+    enabled: bool,
+    normal_impulses: Vec<Real>,
+    tangent_impulses: Vec<Real>,
+    rolling_impulse: Real,
+    // Whether each contact is fast enough to bounce
+    bounces: Vec<bool>,
+    // Contact points in the local space of each body, relative to its center of mass
+    local_anchors: Vec<(Vector2d<Real>, Vector2d<Real>)>,
+}
+
+// Closest point to `p` on the segment from `a` to `b`
+fn closest_point_on_segment(
+    p: Vector2d<Real>,
+    a: Vector2d<Real>,
+    b: Vector2d<Real>,
+) -> Vector2d<Real> {
+    let ab = b - a;
+    if ab.len_square() == real(0.0) {
+        return a;
+    }
+    let t = ((p - a) * ab / ab.len_square()).clamp(real(0.0), real(1.0));
+    a + ab * t
+}
+
+// Closest points between segments p1-q1 and p2-q2, the first one lies on p1-q1
+fn closest_points_on_segments(
+    p1: Vector2d<Real>,
+    q1: Vector2d<Real>,
+    p2: Vector2d<Real>,
+    q2: Vector2d<Real>,
+) -> (Vector2d<Real>, Vector2d<Real>) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.len_square();
+    let e = d2.len_square();
+    let f = d2 * r;
+    if a == real(0.0) {
+        return (p1, closest_point_on_segment(p1, p2, q2));
+    }
+    if e == real(0.0) {
+        return (closest_point_on_segment(p2, p1, q1), p2);
+    }
+    let b = d1 * d2;
+    let c = d1 * r;
+    let denominator = a * e - b * b;
+    // Parallel segments have no unique answer, start from p1
+    let mut s = if denominator != real(0.0) {
+        ((b * f - c * e) / denominator).clamp(real(0.0), real(1.0))
+    } else {
+        real(0.0)
+    };
+    let mut t = (b * s + f) / e;
+    if t < real(0.0) {
+        t = real(0.0);
+        s = (-c / a).clamp(real(0.0), real(1.0));
+    } else if t > real(1.0) {
+        t = real(1.0);
+        s = ((b - c) / a).clamp(real(0.0), real(1.0));
+    }
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+// Radius a shape rolls on, zero for shapes with corners
+fn rolling_radius(body: &dyn RigidBody) -> Real {
+    if let Some(circle) = body.downcast_ref::<Circle>() {
+        circle.radius
+    } else if let Some(capsule) = body.downcast_ref::<Capsule>() {
+        capsule.radius
+    } else {
+        real(0.0)
+    }
 }
 
 impl Manifold {
-    pub fn draw(&self, canvas: &mut Canvas) {
-        canvas.context.set_stroke_style_color("red");
-        canvas.context.set_fill_style_color("red");
+    fn new(
+        object_a: &Rc<RefCell<Object>>,
+        object_b: &Rc<RefCell<Object>>,
+        penetration: Real,
+        normal: Vector2d<Real>,
+        contacts: Vec<Vector2d<Real>>,
+    ) -> Self {
+        let material =
+            ContactMaterial::mix(&object_a.borrow().material, &object_b.borrow().material);
+        let tangent_speed = object_a.borrow().tangent_speed + object_b.borrow().tangent_speed;
+        let count = contacts.len();
+        let local_anchors = {
+            let a = object_a.borrow();
+            let b = object_b.borrow();
+            contacts
+                .iter()
+                .map(|&contact| {
+                    (
+                        (contact - a.world_center()).rotate(-a.orient),
+                        (contact - b.world_center()).rotate(-b.orient),
+                    )
+                })
+                .collect()
+        };
+        Manifold {
+            object_a: object_a.clone(),
+            object_b: object_b.clone(),
+            penetration,
+            normal,
+            contacts,
+            mixed_restitution: material.restitution,
+            mixed_dynamic_friction: material.dynamic_friction,
+            mixed_static_friction: material.static_friction,
+            mixed_rolling_resistance: material.rolling_resistance,
+            rolling_radius: real(0.0),
+            tangent_speed,
+            enabled: true,
+            normal_impulses: vec![real(0.0); count],
+            tangent_impulses: vec![real(0.0); count],
+            rolling_impulse: real(0.0),
+            bounces: vec![true; count],
+            local_anchors,
+        }
+    }
+
+    pub fn object_a(&self) -> &Rc<RefCell<Object>> {
+        &self.object_a
+    }
+
+    pub fn object_b(&self) -> &Rc<RefCell<Object>> {
+        &self.object_b
+    }
+
+    pub fn penetration(&self) -> Real {
+        self.penetration
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // A disabled contact is skipped by the solver for this step
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn restitution(&self) -> Real {
+        self.mixed_restitution
+    }
+
+    pub fn set_restitution(&mut self, restitution: Real) {
+        self.mixed_restitution = restitution;
+    }
+
+    pub fn static_friction(&self) -> Real {
+        self.mixed_static_friction
+    }
+
+    pub fn dynamic_friction(&self) -> Real {
+        self.mixed_dynamic_friction
+    }
+
+    pub fn set_friction(&mut self, static_friction: Real, dynamic_friction: Real) {
+        self.mixed_static_friction = static_friction;
+        self.mixed_dynamic_friction = dynamic_friction;
+    }
+
+    // Speed at which the two surfaces slide past each other when friction holds, the sum of the
+    // bodies' tangent speeds
+    pub fn tangent_speed(&self) -> Real {
+        self.tangent_speed
+    }
+
+    pub fn set_tangent_speed(&mut self, tangent_speed: Real) {
+        self.tangent_speed = tangent_speed;
+    }
+
+    pub fn set_material(&mut self, material: ContactMaterial) {
+        self.mixed_static_friction = material.static_friction;
+        self.mixed_dynamic_friction = material.dynamic_friction;
+        self.mixed_restitution = material.restitution;
+        self.mixed_rolling_resistance = material.rolling_resistance;
+    }
+
+    pub fn rolling_resistance(&self) -> Real {
+        self.mixed_rolling_resistance
+    }
+
+    pub fn set_rolling_resistance(&mut self, rolling_resistance: Real) {
+        self.mixed_rolling_resistance = rolling_resistance;
+    }
+
+    // Total impulse applied along the normal at each contact point during the last step
+    pub fn normal_impulses(&self) -> &[Real] {
+        &self.normal_impulses
+    }
+
+    // Total magnitude of the friction impulse at each contact point during the last step
+    pub fn tangent_impulses(&self) -> &[Real] {
+        &self.tangent_impulses
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        frame.context.set_stroke_style_color("red");
+        frame.context.set_fill_style_color("red");
         for contact in &self.contacts {
-            canvas.context.fill_rect(
-                (contact.x - 0.04) * canvas.scaled_width,
-                (contact.y - 0.04) * canvas.scaled_height,
-                0.08 * canvas.scaled_width,
-                0.08 * canvas.scaled_width,
+            let contact = contact.to_f64();
+            frame.context.fill_rect(
+                (contact.x - 0.04) * frame.scaled_width,
+                (contact.y - 0.04) * frame.scaled_height,
+                0.08 * frame.scaled_width,
+                0.08 * frame.scaled_width,
             );
         }
     }
-    pub fn position_correction(&mut self) {
-        let a_inverse_mass = self.object_a.borrow().inverse_mass;
-        let b_inverse_mass = self.object_b.borrow().inverse_mass;
+    // Velocity of B relative to A at a contact point
+    fn relative_velocity(a: &Object, b: &Object, contact: Vector2d<Real>) -> Vector2d<Real> {
+        let r_a = contact - a.world_center();
+        let r_b = contact - b.world_center();
+        b.velocity + b.angular_velocity.cross_product(r_b)
+            - a.velocity
+            - a.angular_velocity.cross_product(r_a)
+    }
+
+    // Whether a one-way platform in this contact lets the other body through, because the body
+    // is not on top of it or is still moving up
+    pub fn passes_one_way(&self) -> bool {
+        let object_a = self.object_a.borrow();
+        let object_b = self.object_b.borrow();
+        // Normal pointing from the platform to the body, and velocity of the body
+        let (platform, normal, sign) = if object_a.one_way {
+            (&object_a, self.normal, real(1.0))
+        } else if object_b.one_way {
+            (&object_b, -self.normal, real(-1.0))
+        } else {
+            return false;
+        };
+        let up = Vector2d::new(real(0.0), real(-1.0)).rotate(platform.orient);
+        if normal * up < real(0.5) {
+            return true;
+        }
+        let k_threshold = real(0.5);
+        self.contacts.iter().any(|&contact| {
+            Self::relative_velocity(&object_a, &object_b, contact) * up * sign > k_threshold
+        })
+    }
+
+    // One non-linear Gauss-Seidel pass: recompute the separation of every contact from the
+    // current transforms and push the bodies apart by `rate` of what exceeds `slop`.
+    // Returns the smallest separation found.
+    pub fn solve_position(&mut self, slop: Real, rate: Real) -> Real {
+        if !self.enabled {
+            return real(0.0);
+        }
+        let k_max_correction = real(0.2); // Largest push in one pass, avoids overshooting
         let mut object_a = self.object_a.borrow_mut();
         let mut object_b = self.object_b.borrow_mut();
-        let k_slop = 0.005; // Penetration allowance
-        let percent = 0.4; // Penetration percentage to correct
-        let correction = self.normal
-            * percent
-            * ((self.penetration - k_slop).max(0.0) / (a_inverse_mass + b_inverse_mass));
-        object_a.position -= correction * a_inverse_mass;
-        object_b.position += correction * b_inverse_mass;
+        let mut min_separation: Real = real(0.0);
+        for &(anchor_a, anchor_b) in &self.local_anchors {
+            let r_a = anchor_a.rotate(object_a.orient);
+            let r_b = anchor_b.rotate(object_b.orient);
+            // The contact points started at the same place with `penetration` overlap
+            let d = (object_b.world_center() + r_b) - (object_a.world_center() + r_a);
+            let separation = d * self.normal - self.penetration;
+            min_separation = min_separation.min(separation);
+
+            let correction = (rate * (separation + slop)).clamp(-k_max_correction, real(0.0));
+            let ra_cross_n = r_a.cross_product(self.normal);
+            let rb_cross_n = r_b.cross_product(self.normal);
+            let k = object_a.inverse_mass
+                + object_b.inverse_mass
+                + ra_cross_n * ra_cross_n * object_a.inverse_inertia
+                + rb_cross_n * rb_cross_n * object_b.inverse_inertia;
+            if k <= real(0.0) {
+                continue;
+            }
+            let impulse = self.normal * (-correction / k);
+            object_a.apply_position_impulse(-impulse, r_a);
+            object_b.apply_position_impulse(impulse, r_b);
+        }
+        min_separation
+    }
+
+    // Call before solving, contacts approaching slower than `restitution_threshold` do not
+    // bounce so resting bodies settle
+    pub fn prepare(&mut self, restitution_threshold: Real) {
+        let object_a = self.object_a.borrow();
+        let object_b = self.object_b.borrow();
+        for (i, &contact) in self.contacts.iter().enumerate() {
+            let contact_velocity =
+                Self::relative_velocity(&object_a, &object_b, contact) * self.normal;
+            self.bounces[i] = -contact_velocity >= restitution_threshold;
+        }
     }
 
     pub fn apply_impulse(&mut self) {
+        if !self.enabled {
+            return;
+        }
         let mut object_a = self.object_a.borrow_mut();
         let mut object_b = self.object_b.borrow_mut();
-        if object_a.inverse_mass + object_b.inverse_mass < 0.01 {
-            object_a.velocity.set(0.0, 0.0);
-            object_b.velocity.set(0.0, 0.0);
+
+        // Rolling resistance opposes the relative spin, with a torque limited by the normal
+        // impulse applied so far acting on the rolling radius
+        let inverse_inertia = object_a.inverse_inertia + object_b.inverse_inertia;
+        if self.mixed_rolling_resistance > real(0.0) && inverse_inertia > real(0.0) {
+            let normal_impulse: Real = self.normal_impulses.iter().sum();
+            let max_impulse = self.mixed_rolling_resistance * self.rolling_radius * normal_impulse;
+            let old_impulse = self.rolling_impulse;
+            let spin = object_b.angular_velocity - object_a.angular_velocity;
+            self.rolling_impulse =
+                (old_impulse - spin / inverse_inertia).clamp(-max_impulse, max_impulse);
+            let impulse = self.rolling_impulse - old_impulse;
+            let turn_a = impulse * object_a.inverse_inertia;
+            let turn_b = impulse * object_b.inverse_inertia;
+            object_a.angular_velocity -= turn_a;
+            object_b.angular_velocity += turn_b;
         }
 
-        for &contact in &self.contacts {
+        for (i, &contact) in self.contacts.iter().enumerate() {
             // Vectors from center of mas to contact point
-            let r_a = contact - object_a.position;
-            let r_b = contact - object_b.position;
+            let r_a = contact - object_a.world_center();
+            let r_b = contact - object_b.world_center();
 
             // Relative velocity at contact point
-            let v_ab = object_b.velocity + object_b.angular_velocity.cross_product(r_b)
-                - object_a.velocity
-                - object_a.angular_velocity.cross_product(r_a);
+            let v_ab = Self::relative_velocity(&object_a, &object_b, contact);
 
             // Relative velocity along the normal
             let contact_velocity = v_ab * self.normal;
 
             // Do not resolve if velocities are separating
-            if contact_velocity > 0.0 {
+            if contact_velocity > real(0.0) {
                 return;
             }
             let ra_cross_n = r_a.cross_product(self.normal);
@@ -81,34 +362,45 @@ impl Manifold {
                 + ra_cross_n * ra_cross_n * object_a.inverse_inertia
                 + rb_cross_n * rb_cross_n * object_b.inverse_inertia;
 
-            let j = -(1.0 + self.mixed_restitution) * contact_velocity
+            let restitution = if self.bounces[i] {
+                self.mixed_restitution
+            } else {
+                real(0.0)
+            };
+            let j = -(real(1.0) + restitution) * contact_velocity
                 / inv_mass_inertia
-                / self.contacts.len() as f64;
+                / real(self.contacts.len() as f64);
 
             let impulse = self.normal * j;
             object_a.apply_impulse(-impulse, r_a);
             object_b.apply_impulse(impulse, r_b);
+            self.normal_impulses[i] += j;
 
-            // Friction impulse
+            // Friction impulse, moving surfaces drag the other body along at the tangent speed
+            let surface_velocity =
+                Vector2d::new(-self.normal.y, self.normal.x) * self.tangent_speed;
+            let v_ab = v_ab - surface_velocity;
             let mut t = v_ab - (self.normal * (v_ab * self.normal));
-            if t.len() < 0.01 {
+            if t.len() < real(0.01) {
                 return;
             }
             t = t.normalize();
+            // Anisotropic friction depends on the sliding direction in each body's frame
+            let friction_scale = object_a.friction_scale(t) * object_b.friction_scale(t);
 
             // j tangent magnitude
-            let jt = -v_ab * t / inv_mass_inertia / self.contacts.len() as f64;
+            let jt = -v_ab * t / inv_mass_inertia / real(self.contacts.len() as f64);
             //console!(log, "jt: %f", jt);
-            if jt.abs() < 0.01 {
+            if jt.abs() < real(0.01) {
                 return;
             }
 
             // Coulumb's law
             let tangent_impulse;
-            if jt.abs() < j * self.mixed_static_friction {
+            if jt.abs() < j * self.mixed_static_friction * friction_scale {
                 tangent_impulse = t * jt;
             } else {
-                tangent_impulse = t * (-j) * self.mixed_dynamic_friction;
+                tangent_impulse = t * (-j) * self.mixed_dynamic_friction * friction_scale;
             }
             //console!(
             //log,
@@ -119,42 +411,34 @@ impl Manifold {
 
             object_a.apply_impulse(-tangent_impulse, r_a);
             object_b.apply_impulse(tangent_impulse, r_b);
+            self.tangent_impulses[i] += tangent_impulse.len();
         }
     }
     fn circle_to_circle(a: &Circle, b: &Circle) -> Option<Manifold> {
         let object_a = a.object.borrow();
         let object_b = b.object.borrow();
-        let mixed_restitution = object_a.restitution.min(object_b.restitution);
-        let mixed_static_friction = (object_a.static_friction * object_b.static_friction).sqrt();
-        let mixed_dynamic_friction = (object_a.dynamic_friction * object_b.dynamic_friction).sqrt();
         let normal = object_b.position - object_a.position;
         let radius_sum = a.radius + b.radius;
         if normal.len_square() >= radius_sum * radius_sum {
             return None;
         }
         let distance = normal.len();
-        if distance == 0.0 {
-            Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: a.radius,
-                normal: Vector2d::new(1.0, 0.0),
-                contacts: vec![object_a.position],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            })
+        if distance == real(0.0) {
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                a.radius,
+                Vector2d::new(real(1.0), real(0.0)),
+                vec![object_a.position],
+            ))
         } else {
-            Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: radius_sum - distance,
-                normal: normal / distance,
-                contacts: vec![normal / distance * a.radius + object_a.position],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            })
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                radius_sum - distance,
+                normal / distance,
+                vec![normal / distance * a.radius + object_a.position],
+            ))
         }
     }
 
@@ -162,10 +446,6 @@ impl Manifold {
         let object_a = a.object.borrow();
         let object_b = b.object.borrow();
 
-        let mixed_restitution = object_a.restitution.min(object_b.restitution);
-        let mixed_static_friction = (object_a.static_friction * object_b.static_friction).sqrt();
-        let mixed_dynamic_friction = (object_a.dynamic_friction * object_b.dynamic_friction).sqrt();
-
         // Transform circle center to polygon model space
         let center = (object_a.position - object_b.position).rotate(-object_b.orient);
 
@@ -186,18 +466,15 @@ impl Manifold {
         }
 
         // Check to see if center is within polygon
-        if separation < 0.01 {
+        if separation < real(0.01) {
             let normal = (-b.normals[face_normal]).rotate(object_b.orient);
-            return Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: a.radius,
-                normal: normal,
-                contacts: vec![normal * a.radius + object_a.position],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            });
+            return Some(Manifold::new(
+                &a.object,
+                &b.object,
+                a.radius,
+                normal,
+                vec![normal * a.radius + object_a.position],
+            ));
         }
 
         // Grab face's vertices
@@ -215,55 +492,46 @@ impl Manifold {
         let penetration = a.radius - separation;
 
         // Closest to v1
-        if dot1 <= 0.0 {
+        if dot1 <= real(0.0) {
             if (center - v1).len() > a.radius {
                 return None;
             }
             let normal = (v1 - center).rotate(object_b.orient).normalize();
             let contact = v1.rotate(object_b.orient) + object_b.position;
-            return Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: penetration,
-                normal: normal,
-                contacts: vec![contact],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            });
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                penetration,
+                normal,
+                vec![contact],
+            ))
         }
         // Closest to v2
-        else if dot2 <= 0.0 {
+        else if dot2 <= real(0.0) {
             if (center - v2).len() > a.radius {
                 return None;
             }
             let normal = (v2 - center).rotate(object_b.orient).normalize();
             let contact = v2.rotate(object_b.orient) + object_b.position;
-            return Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: penetration,
-                normal: normal,
-                contacts: vec![contact],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            });
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                penetration,
+                normal,
+                vec![contact],
+            ))
         } else {
             let normal = -(b.normals[face_normal].rotate(object_b.orient));
             //if (center-v1)*n>a.radius{
             //return None;
             //}
-            return Some(Manifold {
-                object_a: a.object.clone(),
-                object_b: b.object.clone(),
-                penetration: penetration,
-                normal: normal,
-                contacts: vec![normal * a.radius + object_a.position],
-                mixed_restitution: mixed_restitution,
-                mixed_dynamic_friction: mixed_dynamic_friction,
-                mixed_static_friction: mixed_static_friction,
-            });
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                penetration,
+                normal,
+                vec![normal * a.radius + object_a.position],
+            ))
         }
     }
 
@@ -271,13 +539,548 @@ impl Manifold {
         Self::circle_to_polygon(b, a)
     }
 
-    // If there is a collision, return the manifold, otherwise return None
-    pub fn solve_collision<'b>(a: &Box<RigidBody + 'b>, b: &Box<RigidBody + 'b>) -> Option<Self> {
+    fn capsule_to_circle(a: &Capsule, b: &Circle) -> Option<Manifold> {
+        let (a1, a2) = a.end_points();
+        let center = b.object.borrow().position;
+        let closest = closest_point_on_segment(center, a1, a2);
+        let normal = center - closest;
+        let radius_sum = a.radius + b.radius;
+        if normal.len_square() >= radius_sum * radius_sum {
+            return None;
+        }
+        let distance = normal.len();
+        if distance == real(0.0) {
+            // Circle center is on the segment, push it out sideways
+            let normal = Vector2d::new(real(0.0), real(1.0)).rotate(a.object.borrow().orient);
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                radius_sum,
+                normal,
+                vec![closest],
+            ))
+        } else {
+            let normal = normal / distance;
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                radius_sum - distance,
+                normal,
+                vec![normal * a.radius + closest],
+            ))
+        }
+    }
+
+    fn circle_to_capsule(a: &Circle, b: &Capsule) -> Option<Manifold> {
+        Self::capsule_to_circle(b, a)
+    }
+
+    fn capsule_to_capsule(a: &Capsule, b: &Capsule) -> Option<Manifold> {
+        let (a1, a2) = a.end_points();
+        let (b1, b2) = b.end_points();
+        let radius_sum = a.radius + b.radius;
+        let (closest_a, closest_b) = closest_points_on_segments(a1, a2, b1, b2);
+        let normal = closest_b - closest_a;
+        if normal.len_square() >= radius_sum * radius_sum {
+            return None;
+        }
+        let distance = normal.len();
+        if distance == real(0.0) {
+            // Segments cross each other, push B out along A's side
+            let axis = (a2 - a1).normalize();
+            let mut normal = Vector2d::new(-axis.y, axis.x);
+            if normal * (b.object.borrow().position - a.object.borrow().position) < real(0.0) {
+                normal = -normal;
+            }
+            return Some(Manifold::new(
+                &a.object,
+                &b.object,
+                radius_sum,
+                normal,
+                vec![closest_a],
+            ));
+        }
+        let normal = normal / distance;
+
+        // Nearly parallel capsules touch along a line, use both ends of the overlap
+        let axis_a = a2 - a1;
+        let axis_b = b2 - b1;
+        if axis_a.len_square() > real(0.0)
+            && axis_b.len_square() > real(0.0)
+            && axis_a.cross_product(axis_b).abs() < real(0.05) * axis_a.len() * axis_b.len()
+        {
+            let direction = axis_a.normalize();
+            let low = (a1 * direction).max((b1 * direction).min(b2 * direction));
+            let high = (a2 * direction).min((b1 * direction).max(b2 * direction));
+            if high > low {
+                let mut contacts = Vec::new();
+                let mut penetration = real(0.0);
+                for &t in &[low, high] {
+                    let p = a1 + direction * (t - a1 * direction);
+                    let separation = (closest_point_on_segment(p, b1, b2) - p) * normal;
+                    if separation < radius_sum {
+                        contacts.push(normal * a.radius + p);
+                        penetration += radius_sum - separation;
+                    }
+                }
+                if contacts.len() == 2 {
+                    penetration /= real(contacts.len() as f64);
+                    return Some(Manifold::new(
+                        &a.object,
+                        &b.object,
+                        penetration,
+                        normal,
+                        contacts,
+                    ));
+                }
+            }
+        }
+
+        Some(Manifold::new(
+            &a.object,
+            &b.object,
+            radius_sum - distance,
+            normal,
+            vec![normal * a.radius + closest_a],
+        ))
+    }
+
+    fn capsule_to_polygon(a: &Capsule, b: &Polygon) -> Option<Manifold> {
+        let (a1, a2) = a.end_points();
+        let (normal, penetration, contacts) = Self::segment_to_polygon(a1, a2, a.radius, b)?;
+        Some(Manifold::new(
+            &a.object,
+            &b.object,
+            penetration,
+            normal,
+            contacts,
+        ))
+    }
+
+    fn polygon_to_capsule(a: &Polygon, b: &Capsule) -> Option<Manifold> {
+        Self::capsule_to_polygon(b, a)
+    }
+
+    // Collide the segment p1-q1 (world space) swept by `radius` against a polygon.
+    // Returns the normal pointing from the segment to the polygon, the penetration and the
+    // contact points, all in world space.
+    fn segment_to_polygon(
+        p1: Vector2d<Real>,
+        q1: Vector2d<Real>,
+        radius: Real,
+        b: &Polygon,
+    ) -> Option<(Vector2d<Real>, Real, Vec<Vector2d<Real>>)> {
+        let object_b = b.object.borrow();
+        let count = b.vertices.len();
+
+        // Transform the segment into polygon model space
+        let s1 = (p1 - object_b.position).rotate(-object_b.orient);
+        let s2 = (q1 - object_b.position).rotate(-object_b.orient);
+
+        // Polygon faces as separating axes
+        let mut face_separation = NEG_INFINITY;
+        let mut face_index = 0;
+        for i in 0..count {
+            let s = (b.normals[i] * (s1 - b.vertices[i])).min(b.normals[i] * (s2 - b.vertices[i]));
+            if s > radius {
+                return None;
+            }
+            if s > face_separation {
+                face_separation = s;
+                face_index = i;
+            }
+        }
+
+        // Segment normal as separating axis, pointing from the segment to the polygon
+        let mut segment_separation = NEG_INFINITY;
+        let mut segment_normal = Vector2d::zero();
+        let edge = s2 - s1;
+        if edge.len_square() > real(0.0) {
+            let n = Vector2d::new(edge.y, -edge.x).normalize();
+            for &axis in &[n, -n] {
+                let s = b
+                    .vertices
+                    .iter()
+                    .map(|&v| axis * (v - s1))
+                    .fold(INFINITY, Real::min);
+                if s > segment_separation {
+                    segment_separation = s;
+                    segment_normal = axis;
+                }
+            }
+            if segment_separation > radius {
+                return None;
+            }
+        }
+
+        let mut normal;
+        let mut contacts = Vec::new();
+        let mut penetration = real(0.0);
+        let face_v1 = b.vertices[face_index];
+        let face_v2 = b.vertices[if face_index + 1 < count {
+            face_index + 1
+        } else {
+            0
+        }];
+        let face_normal = b.normals[face_index];
+
+        if face_separation > real(0.0) || segment_separation > real(0.0) {
+            // The segment itself is outside, only the rounded part can touch the polygon
+            let mut closest = (s1, s1);
+            let mut best_distance = INFINITY;
+            for i in 0..count {
+                let v1 = b.vertices[i];
+                let v2 = b.vertices[if i + 1 < count { i + 1 } else { 0 }];
+                let (c1, c2) = closest_points_on_segments(s1, s2, v1, v2);
+                let d = (c2 - c1).len_square();
+                if d < best_distance {
+                    best_distance = d;
+                    closest = (c1, c2);
+                }
+            }
+            let distance = best_distance.sqrt();
+            if distance >= radius {
+                return None;
+            }
+            normal = (closest.1 - closest.0) / distance;
+
+            // Resting on a face, keep both ends of the segment that overlap it
+            if face_separation > real(0.0) && face_normal * normal < real(-0.99) {
+                normal = -face_normal;
+                if let Some((c1, c2)) = Self::clip_segment_to_face(s1, s2, face_v1, face_v2) {
+                    for &p in &[c1, c2] {
+                        let separation = face_normal * (p - face_v1);
+                        if separation < radius {
+                            contacts.push(p - face_normal * radius);
+                            penetration += radius - separation;
+                        }
+                    }
+                }
+            }
+            if contacts.is_empty() {
+                contacts.push(closest.1);
+                penetration = radius - distance;
+            }
+        } else if face_separation >= real(0.95) * segment_separation {
+            // The polygon face is the reference, clip the segment against it
+            normal = -face_normal;
+            if let Some((c1, c2)) = Self::clip_segment_to_face(s1, s2, face_v1, face_v2) {
+                for &p in &[c1, c2] {
+                    let separation = face_normal * (p - face_v1);
+                    if separation <= radius {
+                        contacts.push(p - face_normal * radius);
+                        penetration += radius - separation;
+                    }
+                }
+            }
+            if contacts.is_empty() {
+                let (separation, p) = [s1, s2]
+                    .iter()
+                    .map(|&p| (face_normal * (p - face_v1), p))
+                    .fold(
+                        (INFINITY, s1),
+                        |best, x| if x.0 < best.0 { x } else { best },
+                    );
+                contacts.push(p - face_normal * radius);
+                penetration = radius - separation;
+            }
+        } else {
+            // The segment is the reference, clip the incident polygon face against it
+            normal = segment_normal;
+            let (sum, clipped) = Self::clip_polygon_to_segment(s1, s2, radius, segment_normal, b);
+            penetration = sum;
+            contacts = clipped;
+        }
+        penetration /= real(contacts.len() as f64);
+
+        // Back to world space
+        let normal = normal.rotate(object_b.orient);
+        let contacts = contacts
+            .iter()
+            .map(|&p| p.rotate(object_b.orient) + object_b.position)
+            .collect();
+        Some((normal, penetration, contacts))
+    }
+
+    // Contacts of a polygon against a segment swept by `radius` whose normal is the reference
+    // axis, in polygon model space. Returns the summed penetration and the contact points.
+    fn clip_polygon_to_segment(
+        s1: Vector2d<Real>,
+        s2: Vector2d<Real>,
+        radius: Real,
+        segment_normal: Vector2d<Real>,
+        b: &Polygon,
+    ) -> (Real, Vec<Vector2d<Real>>) {
+        let count = b.vertices.len();
+        let mut incident = 0;
+        let mut min_dot = INFINITY;
+        for (i, &n) in b.normals.iter().enumerate() {
+            if n * segment_normal < min_dot {
+                min_dot = n * segment_normal;
+                incident = i;
+            }
+        }
+        let v1 = b.vertices[incident];
+        let v2 = b.vertices[if incident + 1 < count {
+            incident + 1
+        } else {
+            0
+        }];
+        let mut contacts = Vec::new();
+        let mut penetration = real(0.0);
+        if let Some((c1, c2)) = Self::clip_segment_to_face(v1, v2, s1, s2) {
+            for &p in &[c1, c2] {
+                let separation = segment_normal * (p - s1);
+                if separation <= radius {
+                    contacts.push(p);
+                    penetration += radius - separation;
+                }
+            }
+        }
+        if contacts.is_empty() {
+            let p = b.get_support(-segment_normal);
+            let separation = segment_normal * (p - s1);
+            if separation <= radius {
+                contacts.push(p);
+                penetration = radius - separation;
+            }
+        }
+        (penetration, contacts)
+    }
+
+    // Clip segment p-q to the slab between the side planes of face v1-v2
+    fn clip_segment_to_face(
+        p: Vector2d<Real>,
+        q: Vector2d<Real>,
+        v1: Vector2d<Real>,
+        v2: Vector2d<Real>,
+    ) -> Option<(Vector2d<Real>, Vector2d<Real>)> {
+        let side = (v2 - v1).normalize();
+        let face = Face {
+            v1: p,
+            v2: q,
+            normal: Vector2d::zero(),
+        };
+        let face = Self::clip(-side, -side * v1, face)?;
+        let face = Self::clip(side, side * v2, face)?;
+        Some((face.v1, face.v2))
+    }
+
+    // The normal of an edge pointing towards `point`, and whether `normal` (pointing away
+    // from the edge) leans past a joint that is flat or concave. Such a normal means the body
+    // caught the corner of a neighbouring edge, it must use the face normal instead.
+    fn edge_normal(
+        edge: &Edge,
+        point: Vector2d<Real>,
+        normal: Vector2d<Real>,
+    ) -> (Vector2d<Real>, bool) {
+        let WorldEdge { v0, v1, v2, v3 } = edge.world_vertices();
+        let direction = v2 - v1;
+        let mut edge_normal = Vector2d::new(direction.y, -direction.x).normalize();
+        if edge_normal * (point - v1) < real(0.0) {
+            edge_normal = -edge_normal;
+        }
+        let k_tolerance = real(0.0001);
+        let leans_past_v1 = normal * direction < real(0.0)
+            && v0.is_some_and(|v0| edge_normal * (v0 - v1) > -k_tolerance);
+        let leans_past_v2 = normal * direction > real(0.0)
+            && v3.is_some_and(|v3| edge_normal * (v3 - v2) > -k_tolerance);
+        (edge_normal, leans_past_v1 || leans_past_v2)
+    }
+
+    fn edge_to_circle(a: &Edge, b: &Circle) -> Option<Manifold> {
+        let WorldEdge { v0, v1, v2, v3 } = a.world_vertices();
+        let center = b.object.borrow().position;
+        let e = v2 - v1;
+
+        // Find the closest feature, leaving vertex regions shared with a neighbouring edge
+        // to that edge
+        let closest;
+        if (center - v1) * e <= real(0.0) {
+            if v0.is_some_and(|v0| (v1 - v0) * (v1 - center) > real(0.0)) {
+                return None;
+            }
+            closest = v1;
+        } else if (v2 - center) * e <= real(0.0) {
+            if v3.is_some_and(|v3| (v3 - v2) * (center - v2) > real(0.0)) {
+                return None;
+            }
+            closest = v2;
+        } else {
+            closest = v1 + e * ((center - v1) * e / e.len_square());
+        }
+
+        let normal = center - closest;
+        if normal.len_square() >= b.radius * b.radius {
+            return None;
+        }
+        let distance = normal.len();
+        if distance == real(0.0) {
+            let normal = Vector2d::new(e.y, -e.x).normalize();
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                b.radius,
+                normal,
+                vec![closest],
+            ))
+        } else {
+            Some(Manifold::new(
+                &a.object,
+                &b.object,
+                b.radius - distance,
+                normal / distance,
+                vec![closest],
+            ))
+        }
+    }
+
+    fn circle_to_edge(a: &Circle, b: &Edge) -> Option<Manifold> {
+        Self::edge_to_circle(b, a)
+    }
+
+    fn edge_to_polygon(a: &Edge, b: &Polygon) -> Option<Manifold> {
+        let WorldEdge { v1, v2, .. } = a.world_vertices();
+        let (normal, penetration, contacts) = Self::segment_to_polygon(v1, v2, real(0.0), b)?;
+        let position = b.object.borrow().position;
+        let (edge_normal, snagged) = Self::edge_normal(a, position, normal);
+        if !snagged {
+            return Some(Manifold::new(
+                &a.object,
+                &b.object,
+                penetration,
+                normal,
+                contacts,
+            ));
+        }
+
+        // Use the edge as reference face, in polygon model space
+        let orient = b.object.borrow().orient;
+        let s1 = (v1 - position).rotate(-orient);
+        let s2 = (v2 - position).rotate(-orient);
+        let (penetration, contacts) =
+            Self::clip_polygon_to_segment(s1, s2, real(0.0), edge_normal.rotate(-orient), b);
+        if contacts.is_empty() {
+            return None;
+        }
+        let penetration = penetration / real(contacts.len() as f64);
+        let contacts = contacts
+            .iter()
+            .map(|&p| p.rotate(orient) + position)
+            .collect();
+        Some(Manifold::new(
+            &a.object,
+            &b.object,
+            penetration,
+            edge_normal,
+            contacts,
+        ))
+    }
+
+    fn polygon_to_edge(a: &Polygon, b: &Edge) -> Option<Manifold> {
+        Self::edge_to_polygon(b, a)
+    }
+
+    fn edge_to_capsule(a: &Edge, b: &Capsule) -> Option<Manifold> {
+        let WorldEdge { v1, v2, .. } = a.world_vertices();
+        let (c1, c2) = b.end_points();
+        let (on_edge, on_capsule) = closest_points_on_segments(v1, v2, c1, c2);
+        let normal = on_capsule - on_edge;
+        if normal.len_square() >= b.radius * b.radius {
+            return None;
+        }
+        let distance = normal.len();
+        let (edge_normal, snagged) = Self::edge_normal(a, b.object.borrow().position, normal);
+        let normal = if distance == real(0.0) || snagged {
+            edge_normal
+        } else {
+            normal / distance
+        };
+
+        // Lying on the edge, keep both ends of the capsule that are over it
+        let mut contacts = Vec::new();
+        let mut penetration = real(0.0);
+        if normal * edge_normal > real(0.99) {
+            if let Some((p1, p2)) = Self::clip_segment_to_face(c1, c2, v1, v2) {
+                for &p in &[p1, p2] {
+                    let separation = edge_normal * (p - v1) - b.radius;
+                    if separation < real(0.0) {
+                        contacts.push(p - edge_normal * b.radius);
+                        penetration -= separation;
+                    }
+                }
+            }
+        }
+        if contacts.is_empty() {
+            if distance == real(0.0) {
+                return None;
+            }
+            contacts.push(on_edge);
+            penetration = b.radius - distance;
+        }
+        penetration /= real(contacts.len() as f64);
+        Some(Manifold::new(
+            &a.object,
+            &b.object,
+            penetration,
+            normal,
+            contacts,
+        ))
+    }
+
+    fn capsule_to_edge(a: &Capsule, b: &Edge) -> Option<Manifold> {
+        Self::edge_to_capsule(b, a)
+    }
+
+    // Return the manifolds of every pair of touching parts, empty if there is no collision
+    pub fn solve_collision(a: &dyn RigidBody, b: &dyn RigidBody) -> Vec<Self> {
+        // Chains collide edge by edge
+        if let Some(chain_a) = a.downcast_ref::<Chain>() {
+            return chain_a
+                .edges()
+                .flat_map(|edge| Self::solve_collision(&edge, b))
+                .collect();
+        }
+        if let Some(chain_b) = b.downcast_ref::<Chain>() {
+            return chain_b
+                .edges()
+                .flat_map(|edge| Self::solve_collision(a, &edge))
+                .collect();
+        }
+        // Compounds collide piece by piece
+        if let Some(compound_a) = a.downcast_ref::<Compound>() {
+            return compound_a
+                .pieces
+                .iter()
+                .flat_map(|piece| Self::solve_collision(piece, b))
+                .collect();
+        }
+        if let Some(compound_b) = b.downcast_ref::<Compound>() {
+            return compound_b
+                .pieces
+                .iter()
+                .flat_map(|piece| Self::solve_collision(a, piece))
+                .collect();
+        }
+        Self::solve_shapes(a, b)
+            .map(|mut manifold| {
+                manifold.rolling_radius = rolling_radius(a).max(rolling_radius(b));
+                manifold
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn solve_shapes(a: &dyn RigidBody, b: &dyn RigidBody) -> Option<Self> {
         if let Some(circle_a) = a.downcast_ref::<Circle>() {
             if let Some(circle_b) = b.downcast_ref::<Circle>() {
                 Self::circle_to_circle(circle_a, circle_b)
             } else if let Some(polygon_b) = b.downcast_ref::<Polygon>() {
                 Self::circle_to_polygon(circle_a, polygon_b)
+            } else if let Some(capsule_b) = b.downcast_ref::<Capsule>() {
+                Self::circle_to_capsule(circle_a, capsule_b)
+            } else if let Some(edge_b) = b.downcast_ref::<Edge>() {
+                Self::circle_to_edge(circle_a, edge_b)
             } else {
                 panic!("Unknown RigidBody.");
             }
@@ -286,6 +1089,35 @@ impl Manifold {
                 Self::polygon_to_circle(polygon_a, circle_b)
             } else if let Some(polygon_b) = b.downcast_ref::<Polygon>() {
                 Self::polygon_to_polygon(polygon_a, polygon_b)
+            } else if let Some(capsule_b) = b.downcast_ref::<Capsule>() {
+                Self::polygon_to_capsule(polygon_a, capsule_b)
+            } else if let Some(edge_b) = b.downcast_ref::<Edge>() {
+                Self::polygon_to_edge(polygon_a, edge_b)
+            } else {
+                panic!("Unknown RigidBody.");
+            }
+        } else if let Some(capsule_a) = a.downcast_ref::<Capsule>() {
+            if let Some(circle_b) = b.downcast_ref::<Circle>() {
+                Self::capsule_to_circle(capsule_a, circle_b)
+            } else if let Some(polygon_b) = b.downcast_ref::<Polygon>() {
+                Self::capsule_to_polygon(capsule_a, polygon_b)
+            } else if let Some(capsule_b) = b.downcast_ref::<Capsule>() {
+                Self::capsule_to_capsule(capsule_a, capsule_b)
+            } else if let Some(edge_b) = b.downcast_ref::<Edge>() {
+                Self::capsule_to_edge(capsule_a, edge_b)
+            } else {
+                panic!("Unknown RigidBody.");
+            }
+        } else if let Some(edge_a) = a.downcast_ref::<Edge>() {
+            if let Some(circle_b) = b.downcast_ref::<Circle>() {
+                Self::edge_to_circle(edge_a, circle_b)
+            } else if let Some(polygon_b) = b.downcast_ref::<Polygon>() {
+                Self::edge_to_polygon(edge_a, polygon_b)
+            } else if let Some(capsule_b) = b.downcast_ref::<Capsule>() {
+                Self::edge_to_capsule(edge_a, capsule_b)
+            } else if b.downcast_ref::<Edge>().is_some() {
+                // Edges are static, they never touch each other
+                None
             } else {
                 panic!("Unknown RigidBody.");
             }
@@ -298,16 +1130,12 @@ impl Manifold {
         let object_a = a.object.borrow();
         let object_b = b.object.borrow();
 
-        let mixed_restitution = object_a.restitution.min(object_b.restitution);
-        let mixed_static_friction = (object_a.static_friction * object_b.static_friction).sqrt();
-        let mixed_dynamic_friction = (object_a.dynamic_friction * object_b.dynamic_friction).sqrt();
-
         let (face_a, penetration_a) = Self::find_axis_least_penetration(a, b);
-        if penetration_a >= 0.0 {
+        if penetration_a >= real(0.0) {
             return None;
         }
         let (face_b, penetration_b) = Self::find_axis_least_penetration(b, a);
-        if penetration_b >= 0.0 {
+        if penetration_b >= real(0.0) {
             return None;
         }
 
@@ -317,7 +1145,7 @@ impl Manifold {
         let ref_face;
         let flip;
 
-        if penetration_a >= 0.95 * penetration_b {
+        if penetration_a >= real(0.95) * penetration_b {
             ref_poly = a;
             inc_poly = b;
             ref_object = object_a;
@@ -359,45 +1187,42 @@ impl Manifold {
             ref_face_normal
         };
         let mut contacts = Vec::new();
-        let mut penetration = 0.0;
+        let mut penetration = real(0.0);
 
         let mut separation = (ref_face_normal * clipped_face.v1) - ref_c;
-        if separation <= 0.0 {
+        if separation <= real(0.0) {
             contacts.push(clipped_face.v1);
             penetration = -separation;
         }
 
         separation = (ref_face_normal * clipped_face.v2) - ref_c;
-        if separation <= 0.0 {
+        if separation <= real(0.0) {
             contacts.push(clipped_face.v2);
             penetration += -separation;
         }
-        penetration /= contacts.len() as f64;
+        penetration /= real(contacts.len() as f64);
         //console!(log, "penetration %f", penetration);
 
-        Some(Manifold {
-            object_a: a.object.clone(),
-            object_b: b.object.clone(),
-            penetration: penetration,
-            normal: normal,
-            contacts: contacts,
-            mixed_restitution: mixed_restitution,
-            mixed_dynamic_friction: mixed_dynamic_friction,
-            mixed_static_friction: mixed_static_friction,
-        })
+        Some(Manifold::new(
+            &a.object,
+            &b.object,
+            penetration,
+            normal,
+            contacts,
+        ))
     }
 
-    fn clip(normal: Vector2d<f64>, c: f64, face: Face) -> Option<Face> {
+    fn clip(normal: Vector2d<Real>, c: Real, face: Face) -> Option<Face> {
         let mut out = Vec::new();
         let d1 = normal * face.v1 - c;
         let d2 = normal * face.v2 - c;
-        if d1 <= 0.0 {
+        if d1 <= real(0.0) {
             out.push(face.v1);
         }
-        if d2 <= 0.0 {
+        if d2 <= real(0.0) {
             out.push(face.v2);
         }
-        if d1 * d2 < 0.0 {
+        if d1 * d2 < real(0.0) {
             out.push(face.v1 + (face.v2 - face.v1) * (d1 / (d1 - d2)));
         }
         if out.len() != 2 {
@@ -445,7 +1270,7 @@ impl Manifold {
         }
     }
 
-    fn find_axis_least_penetration(a: &Polygon, b: &Polygon) -> (Face, f64) {
+    fn find_axis_least_penetration(a: &Polygon, b: &Polygon) -> (Face, Real) {
         let mut best_distance = NEG_INFINITY;
         let mut face_index = 0;
         let object_a = a.object.borrow();
@@ -484,3 +1309,174 @@ impl Manifold {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+    use crate::material::Material;
+    use crate::scene::Scene;
+
+    fn floor(material: Material) -> Polygon {
+        let mut floor = Polygon::new_box(
+            Vector2d::new(10.0, 17.0).to_real(),
+            real(9.0),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        floor.set_static();
+        floor.object.borrow_mut().material = material;
+        floor
+    }
+
+    // Horizontal speed of a circle rolling on a floor after a few seconds
+    fn rolled_speed(rolling_resistance: f64, radius: f64) -> f64 {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        let material = Material::new("floor", real(0.8), real(0.6), real(0.0))
+            .with_rolling_resistance(real(rolling_resistance));
+        scene.add_body(Box::new(floor(material)));
+        let ball = Circle::new(real(4.0), real(16.5 - radius), real(radius));
+        let object = ball.object.clone();
+        object.borrow_mut().velocity = Vector2d::new(real(3.0), real(0.0));
+        object.borrow_mut().angular_velocity = real(3.0 / radius);
+        scene.add_body(Box::new(ball));
+        for _ in 0..240 {
+            scene.step();
+        }
+        let velocity = object.borrow().velocity;
+        velocity.x.to_f64()
+    }
+
+    #[test]
+    fn rolling_resistance_stops_a_rolling_circle() {
+        assert!(rolled_speed(0.0, 0.5) > 2.9);
+        for &radius in &[0.25, 0.5, 1.0] {
+            assert!(rolled_speed(0.2, radius).abs() < 0.05, "radius {}", radius);
+        }
+    }
+
+    #[test]
+    fn conveyor_drags_a_resting_box() {
+        for &floor_first in &[true, false] {
+            let mut scene = Scene::from_level(&Level::default()).unwrap();
+            let belt = floor(Material::default());
+            belt.object.borrow_mut().tangent_speed = real(2.0);
+            let block = Polygon::new_box(
+                Vector2d::new(10.0, 16.0).to_real(),
+                real(0.5),
+                real(0.5),
+                real(0.0),
+            )
+            .unwrap();
+            let object = block.object.clone();
+            if floor_first {
+                scene.add_body(Box::new(belt));
+                scene.add_body(Box::new(block));
+            } else {
+                scene.add_body(Box::new(block));
+                scene.add_body(Box::new(belt));
+            }
+            for _ in 0..120 {
+                scene.step();
+            }
+            let velocity = object.borrow().velocity;
+            assert!((velocity.x.to_f64() - 2.0).abs() < 0.1, "{:?}", velocity);
+            assert!(velocity.y.to_f64().abs() < 0.1, "{:?}", velocity);
+        }
+    }
+
+    fn capsule(x: f64, y: f64, orient: f64) -> Capsule {
+        let capsule = Capsule::new(real(x), real(y), real(1.0), real(0.5));
+        capsule.object.borrow_mut().orient = real(orient);
+        capsule
+    }
+
+    // The only manifold between the two bodies, checked in both orders. `normal` points
+    // from `a` to `b`.
+    fn collide(a: &dyn RigidBody, b: &dyn RigidBody, normal: (f64, f64), depth: f64) -> usize {
+        let forward = Manifold::solve_collision(a, b);
+        let backward = Manifold::solve_collision(b, a);
+        assert_eq!(forward.len(), 1);
+        assert_eq!(backward.len(), 1);
+        for manifold in forward.iter().chain(&backward) {
+            let mut expected = Vector2d::new(normal.0, normal.1);
+            if !Rc::ptr_eq(&manifold.object_a, &a.object()) {
+                expected = -expected;
+            }
+            assert!(
+                (manifold.normal.to_f64() - expected).len() < 1e-3,
+                "{:?}",
+                manifold.normal
+            );
+            assert!(
+                (manifold.penetration().to_f64() - depth).abs() < 1e-3,
+                "{:?}",
+                manifold.penetration()
+            );
+        }
+        assert_eq!(forward[0].contacts.len(), backward[0].contacts.len());
+        forward[0].contacts.len()
+    }
+
+    #[test]
+    fn capsule_against_circle() {
+        let a = capsule(5.0, 5.0, 0.0);
+        // Beside the segment and off its end
+        let circle = Circle::new(real(5.5), real(5.9), real(0.5));
+        assert_eq!(collide(&a, &circle, (0.0, 1.0), 0.1), 1);
+        let circle = Circle::new(real(6.6), real(5.6), real(0.5));
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        assert_eq!(collide(&a, &circle, (h, h), 1.0 - 0.6 * 2f64.sqrt()), 1);
+        // Turned upright
+        let a = capsule(5.0, 5.0, std::f64::consts::FRAC_PI_2);
+        let circle = Circle::new(real(5.9), real(5.5), real(0.5));
+        assert_eq!(collide(&a, &circle, (1.0, 0.0), 0.1), 1);
+        let circle = Circle::new(real(7.0), real(5.0), real(0.5));
+        assert!(Manifold::solve_collision(&a, &circle).is_empty());
+    }
+
+    #[test]
+    fn capsule_against_polygon() {
+        let a = capsule(5.0, 5.0, 0.0);
+        // Lying on the top face, touching along its whole flat side
+        let block = Polygon::new_box(
+            Vector2d::new(5.0, 6.4).to_real(),
+            real(2.0),
+            real(1.0),
+            real(0.0),
+        )
+        .unwrap();
+        assert_eq!(collide(&a, &block, (0.0, 1.0), 0.1), 2);
+        // Standing on one corner
+        let block = Polygon::new_box(
+            Vector2d::new(5.0, 5.4 + 2f64.sqrt()).to_real(),
+            real(1.0),
+            real(1.0),
+            real(std::f64::consts::FRAC_PI_4),
+        )
+        .unwrap();
+        assert_eq!(collide(&a, &block, (0.0, 1.0), 0.1), 1);
+        let block = Polygon::new_box(
+            Vector2d::new(5.0, 7.0).to_real(),
+            real(2.0),
+            real(1.0),
+            real(0.0),
+        )
+        .unwrap();
+        assert!(Manifold::solve_collision(&a, &block).is_empty());
+    }
+
+    #[test]
+    fn capsule_against_capsule() {
+        let a = capsule(5.0, 5.0, 0.0);
+        // Parallel, side by side
+        assert_eq!(collide(&a, &capsule(5.5, 5.9, 0.0), (0.0, 1.0), 0.1), 2);
+        // Crossing, the top end of the upright one 0.4 below the middle of the other
+        let b = capsule(5.0, 6.4, std::f64::consts::FRAC_PI_2);
+        assert_eq!(collide(&a, &b, (0.0, 1.0), 0.6), 1);
+        // End to end
+        assert_eq!(collide(&a, &capsule(7.9, 5.0, 0.0), (1.0, 0.0), 0.1), 1);
+        assert!(Manifold::solve_collision(&a, &capsule(5.0, 6.1, 0.0)).is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::math::*;
+use serde::{Deserialize, Serialize};
+
+// How the values of two materials are combined for a contact. When the two materials use
+// different rules, the one listed last wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MixingRule {
+    Average,
+    GeometricMean,
+    Multiply,
+    Min,
+    Max,
+}
+
+impl MixingRule {
+    pub fn mix(self, a: Real, b: Real) -> Real {
+        match self {
+            MixingRule::Average => (a + b) / real(2.0),
+            MixingRule::GeometricMean => (a * b).sqrt(),
+            MixingRule::Multiply => a * b,
+            MixingRule::Min => a.min(b),
+            MixingRule::Max => a.max(b),
+        }
+    }
+}
+
+// Surface properties of a body, materials with the same name are the same material
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Material {
+    pub name: String,
+    pub static_friction: Real,
+    pub dynamic_friction: Real,
+    pub restitution: Real,
+    // The torque that resists rolling is at most this times the normal force times the radius
+    // of the rolling shape
+    pub rolling_resistance: Real,
+    pub friction_mixing: MixingRule,
+    pub restitution_mixing: MixingRule,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new("default", real(0.5), real(0.3), real(0.2))
+    }
+}
+
+impl Material {
+    pub fn new(
+        name: &str,
+        static_friction: Real,
+        dynamic_friction: Real,
+        restitution: Real,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            static_friction,
+            dynamic_friction,
+            restitution,
+            rolling_resistance: real(0.0),
+            friction_mixing: MixingRule::GeometricMean,
+            restitution_mixing: MixingRule::Min,
+        }
+    }
+    pub fn with_mixing(
+        mut self,
+        friction_mixing: MixingRule,
+        restitution_mixing: MixingRule,
+    ) -> Self {
+        self.friction_mixing = friction_mixing;
+        self.restitution_mixing = restitution_mixing;
+        self
+    }
+    pub fn with_rolling_resistance(mut self, rolling_resistance: Real) -> Self {
+        self.rolling_resistance = rolling_resistance;
+        self
+    }
+    // Bouncy and grippy whatever it hits
+    pub fn rubber() -> Self {
+        Self::new("rubber", real(1.0), real(0.8), real(0.8))
+            .with_mixing(MixingRule::Max, MixingRule::Max)
+            .with_rolling_resistance(real(0.05))
+    }
+    // Slippery whatever it hits
+    pub fn ice() -> Self {
+        Self::new("ice", real(0.1), real(0.03), real(0.05))
+            .with_mixing(MixingRule::Min, MixingRule::Min)
+    }
+    pub fn wood() -> Self {
+        Self::new("wood", real(0.5), real(0.4), real(0.3)).with_rolling_resistance(real(0.02))
+    }
+    pub fn metal() -> Self {
+        Self::new("metal", real(0.6), real(0.4), real(0.1)).with_rolling_resistance(real(0.005))
+    }
+}
+
+// Friction and restitution used by a contact
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContactMaterial {
+    pub static_friction: Real,
+    pub dynamic_friction: Real,
+    pub restitution: Real,
+    pub rolling_resistance: Real,
+}
+
+impl ContactMaterial {
+    pub fn mix(a: &Material, b: &Material) -> Self {
+        let friction_mixing = a.friction_mixing.max(b.friction_mixing);
+        let restitution_mixing = a.restitution_mixing.max(b.restitution_mixing);
+        Self {
+            static_friction: friction_mixing.mix(a.static_friction, b.static_friction),
+            dynamic_friction: friction_mixing.mix(a.dynamic_friction, b.dynamic_friction),
+            restitution: restitution_mixing.mix(a.restitution, b.restitution),
+            // Any resisting surface slows down rolling
+            rolling_resistance: a.rolling_resistance.max(b.rolling_resistance),
+        }
+    }
+}
+
+// Overrides looked up by material name: replacements for whole materials, and fixed values for
+// contacts between two materials
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialTable {
+    materials: Vec<Material>,
+    pairs: Vec<(String, String, ContactMaterial)>,
+}
+
+impl MaterialTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Bodies whose material has the same name use this one instead
+    pub fn set_material(&mut self, material: Material) {
+        match self.materials.iter_mut().find(|m| m.name == material.name) {
+            Some(m) => *m = material,
+            None => self.materials.push(material),
+        }
+    }
+    // Contacts between the two materials, in either order, use these values without mixing
+    pub fn set_pair(&mut self, a: &str, b: &str, contact: ContactMaterial) {
+        match self.find_pair(a, b) {
+            Some(i) => self.pairs[i].2 = contact,
+            None => self.pairs.push((a.to_string(), b.to_string(), contact)),
+        }
+    }
+    fn find_pair(&self, a: &str, b: &str) -> Option<usize> {
+        self.pairs
+            .iter()
+            .position(|p| (p.0 == a && p.1 == b) || (p.0 == b && p.1 == a))
+    }
+    pub fn material<'a>(&'a self, material: &'a Material) -> &'a Material {
+        self.materials
+            .iter()
+            .find(|m| m.name == material.name)
+            .unwrap_or(material)
+    }
+    pub fn mix(&self, a: &Material, b: &Material) -> ContactMaterial {
+        match self.find_pair(&a.name, &b.name) {
+            Some(i) => self.pairs[i].2,
+            None => ContactMaterial::mix(self.material(a), self.material(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Real, b: f64) -> bool {
+        (a.to_f64() - b).abs() < 1e-3
+    }
+
+    fn with_rule(name: &str, friction: f64, rule: MixingRule) -> Material {
+        Material::new(name, real(friction), real(friction), real(friction)).with_mixing(rule, rule)
+    }
+
+    #[test]
+    fn mixing_rules() {
+        let (a, b) = (real(0.25), real(0.64));
+        assert!(close(MixingRule::Average.mix(a, b), 0.445));
+        assert!(close(MixingRule::GeometricMean.mix(a, b), 0.4));
+        assert!(close(MixingRule::Multiply.mix(a, b), 0.16));
+        assert!(close(MixingRule::Min.mix(a, b), 0.25));
+        assert!(close(MixingRule::Max.mix(a, b), 0.64));
+    }
+
+    #[test]
+    fn rule_listed_last_wins() {
+        let rules = [
+            MixingRule::Average,
+            MixingRule::GeometricMean,
+            MixingRule::Multiply,
+            MixingRule::Min,
+            MixingRule::Max,
+        ];
+        for (i, &first) in rules.iter().enumerate() {
+            for &last in &rules[i..] {
+                let a = with_rule("a", 0.25, first);
+                let b = with_rule("b", 0.64, last);
+                let expected = last.mix(real(0.25), real(0.64)).to_f64();
+                // Whichever body the materials are on
+                for contact in &[ContactMaterial::mix(&a, &b), ContactMaterial::mix(&b, &a)] {
+                    assert!(close(contact.static_friction, expected), "{:?}", last);
+                    assert!(close(contact.dynamic_friction, expected), "{:?}", last);
+                    assert!(close(contact.restitution, expected), "{:?}", last);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_beat_materials_by_name() {
+        let mut table = MaterialTable::new();
+        table.set_material(with_rule("ice", 0.02, MixingRule::Min));
+        let pair = ContactMaterial {
+            static_friction: real(0.9),
+            dynamic_friction: real(0.8),
+            restitution: real(0.1),
+            rolling_resistance: real(0.0),
+        };
+        table.set_pair("ice", "rubber", pair);
+        let ice = Material::new("ice", real(0.5), real(0.5), real(0.5));
+        let rubber = Material::rubber();
+        assert_eq!(table.mix(&ice, &rubber), pair);
+        assert_eq!(table.mix(&rubber, &ice), pair);
+        // Other contacts with ice use the table's ice
+        let contact = table.mix(&ice, &Material::wood());
+        assert!(close(contact.static_friction, 0.02));
+    }
+
+    #[test]
+    fn missing_names_fall_back_to_the_default() {
+        let mut table = MaterialTable::new();
+        table.set_material(Material::ice());
+        let wood = Material::wood();
+        assert_eq!(*table.material(&wood), wood);
+        let default = Material::default();
+        assert_eq!(
+            table.mix(&default, &wood),
+            ContactMaterial::mix(&default, &wood)
+        );
+        // Fields left out of a file are the default material's
+        let named: Material = serde_json::from_str(r#"{ "name": "stone" }"#).unwrap();
+        assert_eq!(
+            named,
+            Material {
+                name: "stone".to_string(),
+                ..default
+            }
+        );
+    }
+}
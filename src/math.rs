@@ -1,23 +1,135 @@
-pub use num_traits::float::Float;
-use num_traits::Zero;
-pub use std::f64::{INFINITY, NEG_INFINITY};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Copy, Clone, PartialEq)]
-pub struct Vector2d<T: Float> {
+// The operations the engine needs from its number type
+pub trait Scalar:
+    Copy
+    + PartialOrd
+    + fmt::Debug
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+{
+    const ZERO: Self;
+    const INFINITY: Self;
+    const NEG_INFINITY: Self;
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    // Used for every rotation, software trigonometry with the `deterministic` feature
+    fn sin_cos(self) -> (Self, Self);
+    fn abs(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+    fn round(self) -> Self;
+    fn is_finite(self) -> bool;
+    fn is_infinite(self) -> bool;
+}
+
+macro_rules! impl_scalar_for_float {
+    ($t:ident) => {
+        impl Scalar for $t {
+            const ZERO: Self = 0.0;
+            const INFINITY: Self = $t::INFINITY;
+            const NEG_INFINITY: Self = $t::NEG_INFINITY;
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+            fn to_f64(self) -> f64 {
+                f64::from(self)
+            }
+            fn sqrt(self) -> Self {
+                $t::sqrt(self)
+            }
+            fn sin(self) -> Self {
+                $t::sin(self)
+            }
+            fn cos(self) -> Self {
+                $t::cos(self)
+            }
+            #[cfg(not(feature = "deterministic"))]
+            fn sin_cos(self) -> (Self, Self) {
+                $t::sin_cos(self)
+            }
+            #[cfg(feature = "deterministic")]
+            fn sin_cos(self) -> (Self, Self) {
+                let (s, c) = sin_cos(f64::from(self));
+                (Self::from_f64(s), Self::from_f64(c))
+            }
+            fn abs(self) -> Self {
+                $t::abs(self)
+            }
+            fn min(self, other: Self) -> Self {
+                $t::min(self, other)
+            }
+            fn max(self, other: Self) -> Self {
+                $t::max(self, other)
+            }
+            fn clamp(self, min: Self, max: Self) -> Self {
+                $t::clamp(self, min, max)
+            }
+            fn round(self) -> Self {
+                $t::round(self)
+            }
+            fn is_finite(self) -> bool {
+                $t::is_finite(self)
+            }
+            fn is_infinite(self) -> bool {
+                $t::is_infinite(self)
+            }
+        }
+    };
+}
+
+impl_scalar_for_float!(f32);
+impl_scalar_for_float!(f64);
+
+// The number type of the engine, picked at build time by the `f32` and `fixed` features. There is
+// one per build, so an f32 and a fixed-point engine cannot be used side by side. Only `f32` makes
+// bodies smaller, `Fixed` takes 8 bytes like `f64`.
+#[cfg(all(feature = "f32", feature = "fixed"))]
+compile_error!("the `f32` and `fixed` features pick different number types, enable only one");
+#[cfg(not(any(feature = "f32", feature = "fixed")))]
+pub type Real = f64;
+#[cfg(all(feature = "f32", not(feature = "fixed")))]
+pub type Real = f32;
+#[cfg(feature = "fixed")]
+pub type Real = crate::fixed::Fixed;
+
+pub const INFINITY: Real = <Real as Scalar>::INFINITY;
+pub const NEG_INFINITY: Real = <Real as Scalar>::NEG_INFINITY;
+pub const PI: f64 = std::f64::consts::PI;
+
+// Constants are written as `f64` and converted to the engine's number type
+pub fn real(x: f64) -> Real {
+    Real::from_f64(x)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vector2d<T: Scalar> {
     pub x: T,
     pub y: T,
 }
 
-impl<T: Float> Vector2d<T> {
+impl<T: Scalar> Vector2d<T> {
     pub fn new(x: T, y: T) -> Self {
         Self { x, y }
     }
 
     pub fn zero() -> Self {
         Self {
-            x: Zero::zero(),
-            y: Zero::zero(),
+            x: T::ZERO,
+            y: T::ZERO,
         }
     }
 
@@ -37,14 +149,22 @@ impl<T: Float> Vector2d<T> {
         }
     }
 
+    // For the canvas, which works in `f64`
+    pub fn to_f64(self) -> Vector2d<f64> {
+        Vector2d::new(self.x.to_f64(), self.y.to_f64())
+    }
+
+    pub fn to_real(self) -> Vector2d<Real> {
+        Vector2d::new(real(self.x.to_f64()), real(self.y.to_f64()))
+    }
+
     pub fn set(&mut self, x: T, y: T) {
         self.x = x;
         self.y = y;
     }
 
     pub fn rotate(&self, radians: T) -> Vector2d<T> {
-        let c = radians.cos();
-        let s = radians.sin();
+        let (s, c) = radians.sin_cos();
         Self {
             x: self.x * c - self.y * s,
             y: self.x * s + self.y * c,
@@ -52,21 +172,66 @@ impl<T: Float> Vector2d<T> {
     }
 }
 
-impl Mul<Vector2d<f64>> for f64 {
-    type Output = Vector2d<f64>;
-    fn mul(self, rhs: Vector2d<f64>) -> Self::Output {
+// Sine and cosine built only from additions, multiplications and divisions, which IEEE 754
+// rounds the same way on every platform unlike the system's math library. The kernels are
+// the polynomials from fdlibm, accurate to about 1 ulp for reasonable angles.
+#[cfg(feature = "deterministic")]
+pub fn sin_cos(x: f64) -> (f64, f64) {
+    // Reduce to [-pi/4, pi/4] around the nearest multiple of pi/2, in two steps so little
+    // precision is lost
+    const PIO2_1: f64 = 1.570_796_326_734_125_6;
+    const PIO2_1T: f64 = 6.077_100_506_506_192e-11;
+    let k = (x * std::f64::consts::FRAC_2_PI).round();
+    let r = (x - k * PIO2_1) - k * PIO2_1T;
+
+    let z = r * r;
+    let sin = {
+        const S1: f64 = -1.666_666_666_666_663_2e-1;
+        const S2: f64 = 8.333_333_333_322_49e-3;
+        const S3: f64 = -1.984_126_982_985_795e-4;
+        const S4: f64 = 2.755_731_370_707_006_8e-6;
+        const S5: f64 = -2.505_076_025_340_686_3e-8;
+        const S6: f64 = 1.589_690_995_211_55e-10;
+        let w = S2 + z * (S3 + z * (S4 + z * (S5 + z * S6)));
+        r + z * r * (S1 + z * w)
+    };
+    let cos = {
+        const C1: f64 = 4.166_666_666_666_66e-2;
+        const C2: f64 = -1.388_888_888_887_411e-3;
+        const C3: f64 = 2.480_158_728_947_673e-5;
+        const C4: f64 = -2.755_731_435_139_066_3e-7;
+        const C5: f64 = 2.087_572_321_298_175e-9;
+        const C6: f64 = -1.135_964_755_778_819_5e-11;
+        let w = z * z;
+        let poly = z * (C1 + z * (C2 + z * C3)) + w * w * (C4 + z * (C5 + z * C6));
+        let half_z = 0.5 * z;
+        let one_minus = 1.0 - half_z;
+        one_minus + (((1.0 - one_minus) - half_z) + z * poly)
+    };
+
+    match (k as i64) & 3 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+impl Mul<Vector2d<Real>> for Real {
+    type Output = Vector2d<Real>;
+    fn mul(self, rhs: Vector2d<Real>) -> Self::Output {
         rhs * self
     }
 }
 
-impl<T: Float> Mul for Vector2d<T> {
+impl<T: Scalar> Mul for Vector2d<T> {
     type Output = T;
     fn mul(self, rhs: Self) -> T {
         self.x * rhs.x + self.y * rhs.y
     }
 }
 
-impl<T: Float> Mul<T> for Vector2d<T> {
+impl<T: Scalar> Mul<T> for Vector2d<T> {
     type Output = Self;
     fn mul(self, rhs: T) -> Self {
         Self {
@@ -76,7 +241,7 @@ impl<T: Float> Mul<T> for Vector2d<T> {
     }
 }
 
-impl<T: Float> Div<T> for Vector2d<T> {
+impl<T: Scalar> Div<T> for Vector2d<T> {
     type Output = Self;
     fn div(self, rhs: T) -> Self {
         Self {
@@ -86,7 +251,7 @@ impl<T: Float> Div<T> for Vector2d<T> {
     }
 }
 
-impl<T: Float> Add for Vector2d<T> {
+impl<T: Scalar> Add for Vector2d<T> {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
         Self {
@@ -96,7 +261,7 @@ impl<T: Float> Add for Vector2d<T> {
     }
 }
 
-impl<T: Float> Sub for Vector2d<T> {
+impl<T: Scalar> Sub for Vector2d<T> {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self {
         Self {
@@ -106,35 +271,35 @@ impl<T: Float> Sub for Vector2d<T> {
     }
 }
 
-impl<T: Float> AddAssign for Vector2d<T> {
+impl<T: Scalar> AddAssign for Vector2d<T> {
     fn add_assign(&mut self, other: Self) {
         self.x = self.x + other.x;
         self.y = self.y + other.y;
     }
 }
 
-impl<T: Float> SubAssign for Vector2d<T> {
+impl<T: Scalar> SubAssign for Vector2d<T> {
     fn sub_assign(&mut self, other: Self) {
         self.x = self.x - other.x;
         self.y = self.y - other.y;
     }
 }
 
-impl<T: Float> MulAssign<T> for Vector2d<T> {
+impl<T: Scalar> MulAssign<T> for Vector2d<T> {
     fn mul_assign(&mut self, rhs: T) {
         self.x = self.x * rhs;
         self.y = self.y * rhs;
     }
 }
 
-impl<T: Float> DivAssign<T> for Vector2d<T> {
+impl<T: Scalar> DivAssign<T> for Vector2d<T> {
     fn div_assign(&mut self, rhs: T) {
         self.x = self.x / rhs;
         self.y = self.y / rhs;
     }
 }
 
-impl<T: Float> Neg for Vector2d<T> {
+impl<T: Scalar> Neg for Vector2d<T> {
     type Output = Vector2d<T>;
     fn neg(self) -> Vector2d<T> {
         Vector2d {
@@ -149,21 +314,21 @@ pub trait CrossProduct<RHS> {
     fn cross_product(self, rhs: RHS) -> Self::Output;
 }
 
-impl<T: Float> CrossProduct<Vector2d<T>> for Vector2d<T> {
+impl<T: Scalar> CrossProduct<Vector2d<T>> for Vector2d<T> {
     type Output = T;
     fn cross_product(self, rhs: Vector2d<T>) -> T {
         self.x * rhs.y - self.y * rhs.x
     }
 }
 
-impl<T: Float> CrossProduct<T> for Vector2d<T> {
+impl<T: Scalar> CrossProduct<T> for Vector2d<T> {
     type Output = Vector2d<T>;
     fn cross_product(self, rhs: T) -> Vector2d<T> {
         Vector2d::new(rhs * self.y, -rhs * self.x)
     }
 }
 
-impl<T: Float> CrossProduct<Vector2d<T>> for T {
+impl<T: Scalar> CrossProduct<Vector2d<T>> for T {
     type Output = Vector2d<T>;
     fn cross_product(self, rhs: Vector2d<T>) -> Vector2d<T> {
         Vector2d::new(-self * rhs.y, self * rhs.x)
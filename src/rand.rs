@@ -1,18 +1,128 @@
 use rand::rngs::OsRng;
 use rand::RngCore;
 
+// PCG32 (XSH RR), the same seed always gives the same numbers on every platform
+#[derive(Clone)]
 pub struct Rng {
-    rng: OsRng,
+    seed: u64,
+    state: u64,
 }
 
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+const INCREMENT: u64 = 1_442_695_040_888_963_407;
+
 impl Rng {
+    // Seeded from the operating system, `seed()` tells which seed was picked
     pub fn new() -> Self {
-        Self {
-            rng: OsRng::new().unwrap(),
-        }
+        Self::with_seed(OsRng::new().unwrap().next_u64())
+    }
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = Self { seed, state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    // How far the sequence has gone, `resume` continues from there
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+    pub fn resume(seed: u64, state: u64) -> Self {
+        Self { seed, state }
     }
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation = (state >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+    // Uniform in [min, max], both included
     pub fn gen_range(&mut self, min: i32, max: i32) -> i32 {
-        let r = self.rng.next_u32();
-        (r % (max - min + 1) as u32) as i32 + min
+        let range = (i64::from(max) - i64::from(min) + 1) as u64;
+        if range > u64::from(u32::MAX) {
+            return self.next_u32() as i32;
+        }
+        let range = range as u32;
+        // Reject the low values that would make some results more likely than others
+        let threshold = range.wrapping_neg() % range;
+        loop {
+            let r = self.next_u32();
+            if r >= threshold {
+                return (i64::from(min) + i64::from(r % range)) as i32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(rng: &mut Rng) -> Vec<u32> {
+        (0..16).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        for &seed in &[0, 1, 42, u64::MAX] {
+            let mut a = Rng::with_seed(seed);
+            let mut b = Rng::with_seed(seed);
+            assert_eq!(sequence(&mut a), sequence(&mut b));
+            assert_eq!(a.seed(), seed);
+        }
+        // A resumed generator carries on where the other one is
+        let mut a = Rng::with_seed(7);
+        sequence(&mut a);
+        let mut b = Rng::resume(a.seed(), a.state());
+        assert_eq!(sequence(&mut a), sequence(&mut b));
+    }
+
+    #[test]
+    fn different_seeds_different_sequences() {
+        let sequences: Vec<_> = (0..8)
+            .map(|seed| sequence(&mut Rng::with_seed(seed)))
+            .collect();
+        for (i, a) in sequences.iter().enumerate() {
+            for b in &sequences[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn gen_range_stays_in_bounds() {
+        let mut rng = Rng::with_seed(3);
+        for &(min, max) in &[(0, 199), (-5, 5), (10, 11), (i32::MIN, i32::MAX)] {
+            let mut seen = Vec::new();
+            for _ in 0..1000 {
+                let value = rng.gen_range(min, max);
+                assert!(
+                    value >= min && value <= max,
+                    "{} in [{}, {}]",
+                    value,
+                    min,
+                    max
+                );
+                seen.push(value);
+            }
+            // Both ends come up for small ranges
+            if i64::from(max) - i64::from(min) <= 10 {
+                assert!(
+                    seen.contains(&min) && seen.contains(&max),
+                    "[{}, {}]",
+                    min,
+                    max
+                );
+            }
+        }
+        // A range of a single value
+        for _ in 0..100 {
+            assert_eq!(rng.gen_range(4, 4), 4);
+            assert_eq!(rng.gen_range(-1, -1), -1);
+        }
     }
 }
@@ -0,0 +1,284 @@
+use crate::render::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Draws into an RGBA buffer on the CPU, for frames without a browser. Lines are one pixel wide
+// and not antialiased, and text is not drawn.
+pub struct Raster {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    subpaths: Vec<Vec<(f64, f64)>>,
+    fill: [u8; 4],
+    stroke: [u8; 4],
+}
+
+// Hex colors and the few names the engine uses, anything else is black
+fn rgba(color: &str) -> [u8; 4] {
+    match color {
+        "white" => [255, 255, 255, 255],
+        "red" => [255, 0, 0, 255],
+        _ => match parse_hex_color(color) {
+            Some(color) => [color.r, color.g, color.b, 255],
+            None => [0, 0, 0, 255],
+        },
+    }
+}
+
+impl Raster {
+    // Transparent, like a new canvas
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+            subpaths: Vec::new(),
+            fill: [0, 0, 0, 255],
+            stroke: [0, 0, 0, 255],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Rows from top to bottom, four bytes per pixel
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // None outside the raster
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let mut rgba = [0; 4];
+        rgba.copy_from_slice(&self.pixels[i..i + 4]);
+        Some(rgba)
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    pub fn encode_png(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.write_png(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    fn set(&mut self, x: i64, y: i64, color: [u8; 4]) {
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&color);
+    }
+
+    // Pixels whose centers are inside the rectangle
+    fn fill_pixels(&mut self, x: f64, y: f64, width: f64, height: f64, color: [u8; 4]) {
+        let (w, h) = (f64::from(self.width), f64::from(self.height));
+        let clamp_x = |v: f64| (v - 0.5).ceil().max(0.0).min(w) as u32;
+        let clamp_y = |v: f64| (v - 0.5).ceil().max(0.0).min(h) as u32;
+        for py in clamp_y(y)..clamp_y(y + height) {
+            for px in clamp_x(x)..clamp_x(x + width) {
+                self.set(i64::from(px), i64::from(py), color);
+            }
+        }
+    }
+
+    // Bresenham's line between pixel centers, clipped to the raster first so that bodies far
+    // outside do not cost anything
+    fn line(&mut self, from: (f64, f64), to: (f64, f64)) {
+        let (from, to) = match self.clip(from, to) {
+            Some(line) => line,
+            None => return,
+        };
+        let (mut x0, mut y0) = (from.0.floor() as i64, from.1.floor() as i64);
+        let (x1, y1) = (to.0.floor() as i64, to.1.floor() as i64);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let color = self.stroke;
+        loop {
+            self.set(x0, y0, color);
+            if x0 == x1 && y0 == y1 {
+                break;
+            }
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x0 += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y0 += sy;
+            }
+        }
+    }
+
+    // Liang-Barsky
+    fn clip(&self, from: (f64, f64), to: (f64, f64)) -> Option<((f64, f64), (f64, f64))> {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+        let edges = [
+            (-dx, from.0),
+            (dx, f64::from(self.width) - from.0),
+            (-dy, from.1),
+            (dy, f64::from(self.height) - from.1),
+        ];
+        for &(p, q) in &edges {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(t);
+                } else {
+                    t1 = t1.min(t);
+                }
+            }
+        }
+        if t0 > t1 || !t0.is_finite() || !t1.is_finite() {
+            return None;
+        }
+        Some((
+            (from.0 + t0 * dx, from.1 + t0 * dy),
+            (from.0 + t1 * dx, from.1 + t1 * dy),
+        ))
+    }
+}
+
+impl Context2d for Raster {
+    fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.fill_pixels(x, y, width, height, [0, 0, 0, 0]);
+    }
+    fn set_font(&mut self, _font: &str) {}
+    fn set_fill_style_color(&mut self, color: &str) {
+        self.fill = rgba(color);
+    }
+    fn set_stroke_style_color(&mut self, color: &str) {
+        self.stroke = rgba(color);
+    }
+    fn fill_text(&mut self, _text: &str, _x: f64, _y: f64, _max_width: Option<f64>) {}
+    fn begin_path(&mut self) {
+        self.subpaths.clear();
+    }
+    fn close_path(&mut self) {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            if let Some(&first) = subpath.first() {
+                subpath.push(first);
+            }
+        }
+    }
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.subpaths.push(vec![(x, y)]);
+    }
+    fn line_to(&mut self, x: f64, y: f64) {
+        match self.subpaths.last_mut() {
+            Some(subpath) => subpath.push((x, y)),
+            None => self.subpaths.push(vec![(x, y)]),
+        }
+    }
+    fn stroke(&mut self) {
+        let subpaths = std::mem::take(&mut self.subpaths);
+        for subpath in &subpaths {
+            for segment in subpath.windows(2) {
+                self.line(segment[0], segment[1]);
+            }
+        }
+        self.subpaths = subpaths;
+    }
+    fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        let color = self.fill;
+        self.fill_pixels(x, y, width, height, color);
+    }
+}
+
+// Where frame `index` of a numbered sequence goes, `frame_00000.png`, `frame_00001.png` and so on
+pub fn frame_path(directory: &Path, index: u32) -> PathBuf {
+    directory.join(format!("frame_{:05}.png", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(rgba("#0F8"), [0x00, 0xff, 0x88, 255]);
+        assert_eq!(rgba("white"), [255, 255, 255, 255]);
+        assert_eq!(rgba("red"), [255, 0, 0, 255]);
+        // Anything else is black
+        for color in &["black", "#abcd", "abc", "", "blue"] {
+            assert_eq!(rgba(color), [0, 0, 0, 255], "{}", color);
+        }
+    }
+
+    #[test]
+    fn clips_lines_to_the_raster() {
+        let raster = Raster::new(10, 10);
+        assert_eq!(
+            raster.clip((2.0, 3.0), (7.0, 8.0)),
+            Some(((2.0, 3.0), (7.0, 8.0)))
+        );
+        assert_eq!(
+            raster.clip((-5.0, 5.0), (15.0, 5.0)),
+            Some(((0.0, 5.0), (10.0, 5.0)))
+        );
+        assert_eq!(
+            raster.clip((5.0, 5.0), (5.0, -5.0)),
+            Some(((5.0, 5.0), (5.0, 0.0)))
+        );
+        // Outside, beside the raster and across a corner without touching it
+        assert_eq!(raster.clip((-5.0, 2.0), (-1.0, 8.0)), None);
+        assert_eq!(raster.clip((12.0, 5.0), (12.0, 6.0)), None);
+        assert_eq!(raster.clip((-5.0, 4.0), (4.0, -5.0)), None);
+    }
+
+    #[test]
+    fn fills_are_clamped_to_the_raster() {
+        let mut raster = Raster::new(4, 3);
+        let red = [255, 0, 0, 255];
+        raster.fill_pixels(-10.0, 1.0, 100.0, 100.0, red);
+        for y in 0..3 {
+            for x in 0..4 {
+                let expected = if y >= 1 { red } else { [0; 4] };
+                assert_eq!(raster.pixel(x, y), Some(expected), "({}, {})", x, y);
+            }
+        }
+        // Nothing at all when the rectangle is outside or empty
+        let mut raster = Raster::new(4, 3);
+        raster.fill_pixels(5.0, 0.0, 10.0, 10.0, red);
+        raster.fill_pixels(-20.0, -20.0, 10.0, 10.0, red);
+        raster.fill_pixels(1.0, 1.0, -1.0, 1.0, red);
+        assert!(raster.pixels().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn pixels_outside_are_none() {
+        let raster = Raster::new(4, 3);
+        assert_eq!(raster.pixel(3, 2), Some([0; 4]));
+        assert_eq!(raster.pixel(4, 0), None);
+        assert_eq!(raster.pixel(0, 3), None);
+    }
+}
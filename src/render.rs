@@ -0,0 +1,65 @@
+use crate::body::Color;
+
+// The drawing calls of the canvas 2D API that bodies and contacts use, so the same drawing
+// code works in the browser and anywhere else
+pub trait Context2d {
+    fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64);
+    fn set_font(&mut self, font: &str);
+    fn set_fill_style_color(&mut self, color: &str);
+    fn set_stroke_style_color(&mut self, color: &str);
+    fn fill_text(&mut self, text: &str, x: f64, y: f64, max_width: Option<f64>);
+    fn begin_path(&mut self);
+    fn close_path(&mut self);
+    fn move_to(&mut self, x: f64, y: f64);
+    fn line_to(&mut self, x: f64, y: f64);
+    fn stroke(&mut self);
+    fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64);
+}
+
+// A frame being drawn, world coordinates are multiplied by the scales to get pixels
+pub struct Frame<'a> {
+    pub context: &'a mut dyn Context2d,
+    pub scaled_width: f64,
+    pub scaled_height: f64,
+    // Size in pixels
+    pub width: f64,
+    pub height: f64,
+}
+
+// Colors written #rgb or #rrggbb, like the ones bodies are drawn with
+pub fn parse_hex_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    // from_str_radix would also take a sign
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let width = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(hex.get(i * width..(i + 1) * width)?, 16).ok()?;
+        Some(if width == 1 { value * 17 } else { value })
+    };
+    Some(Color::new(channel(0)?, channel(1)?, channel(2)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(color: &str) -> Option<(u8, u8, u8)> {
+        parse_hex_color(color).map(|c| (c.r, c.g, c.b))
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(rgb("#abc"), Some((0xaa, 0xbb, 0xcc)));
+        assert_eq!(rgb("#aabbcc"), Some((0xaa, 0xbb, 0xcc)));
+        assert_eq!(rgb("#0F8"), Some((0x00, 0xff, 0x88)));
+        for color in &["#abcd", "#ggg", "abc", "#", "", "white", "#é1", "#+1+2+3"] {
+            assert_eq!(rgb(color), None, "{}", color);
+        }
+    }
+}
@@ -0,0 +1,535 @@
+use crate::body::*;
+use crate::geometry::*;
+use crate::level::*;
+use crate::material::*;
+use crate::math::*;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+// A level read from a R.U.B.E. (Box2D) JSON scene, with everything that could not be imported
+// described in `warnings`.
+// Box2D's y points up and this engine's points down, so y coordinates, angles and angular
+// velocities are flipped.
+pub struct RubeImport {
+    pub level: Level,
+    pub warnings: Vec<String>,
+}
+
+const STATIC_BODY: i64 = 0;
+const KINEMATIC_BODY: i64 = 1;
+
+// RUBE can write floats as the hex bits of an `f32`
+fn float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => u32::from_str_radix(s, 16)
+            .ok()
+            .map(|bits| f32::from_bits(bits) as f64),
+        _ => None,
+    }
+}
+
+// RUBE writes zero vectors as a plain 0
+fn vector(value: &Value) -> Option<Vector2d<f64>> {
+    match value {
+        Value::Object(_) => Some(Vector2d::new(float(&value["x"])?, float(&value["y"])?)),
+        _ => float(value).filter(|&f| f == 0.0).map(|_| Vector2d::zero()),
+    }
+}
+
+fn vertices(value: &Value) -> Option<Vec<Vector2d<f64>>> {
+    let xs = value["x"].as_array()?;
+    let ys = value["y"].as_array()?;
+    if xs.len() != ys.len() {
+        return None;
+    }
+    xs.iter()
+        .zip(ys)
+        .map(|(x, y)| Some(Vector2d::new(float(x)?, float(y)?)))
+        .collect()
+}
+
+fn flip(v: Vector2d<f64>) -> Vector2d<f64> {
+    Vector2d::new(v.x, -v.y)
+}
+
+// Reads values of one body or fixture, and warns about the ones that have the wrong type
+struct Reader<'a> {
+    value: &'a Value,
+    name: String,
+    warnings: &'a mut Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn float(&mut self, key: &str, default: f64) -> f64 {
+        let value = &self.value[key];
+        if value.is_null() {
+            return default;
+        }
+        float(value).unwrap_or_else(|| {
+            self.warnings.push(format!(
+                "{}: {} is not a number, using {}",
+                self.name, key, default
+            ));
+            default
+        })
+    }
+    fn vector(&mut self, key: &str) -> Vector2d<f64> {
+        let value = &self.value[key];
+        if value.is_null() {
+            return Vector2d::zero();
+        }
+        vector(value).unwrap_or_else(|| {
+            self.warnings
+                .push(format!("{}: {} is not a vector, using 0", self.name, key));
+            Vector2d::zero()
+        })
+    }
+    fn int(&self, key: &str, default: i64) -> i64 {
+        self.value[key].as_i64().unwrap_or(default)
+    }
+    fn bool(&self, key: &str) -> bool {
+        self.value[key].as_bool().unwrap_or(false)
+    }
+    fn warn(&mut self, message: &str) {
+        self.warnings.push(format!("{}: {}", self.name, message));
+    }
+}
+
+fn describe(value: &Value, kind: &str, index: usize) -> String {
+    match value["name"].as_str() {
+        Some(name) => format!("{} {} \"{}\"", kind, index, name),
+        None => format!("{} {}", kind, index),
+    }
+}
+
+impl RubeImport {
+    pub fn from_json(text: &str) -> Result<Self, LevelError> {
+        let world: Value = serde_json::from_str(text)?;
+        let mut warnings = Vec::new();
+        let mut level = Level::default();
+
+        if let Some(gravity) = vector(&world["gravity"]) {
+            level.gravity = flip(gravity).to_real();
+        }
+        if let Some(steps) = float(&world["stepsPerSecond"]).filter(|&s| s > 0.0) {
+            level.timestep = real(1.0 / steps);
+        }
+        if let Some(iterations) = world["velocityIterations"].as_u64() {
+            level.iterations = iterations as u32;
+        }
+        if let Some(iterations) = world["positionIterations"].as_u64() {
+            level.position_iterations = iterations as u32;
+        }
+
+        let empty = Vec::new();
+        let bodies = world["body"].as_array().unwrap_or(&empty);
+        for (i, body) in bodies.iter().enumerate() {
+            Self::import_body(body, describe(body, "body", i), &mut level, &mut warnings);
+        }
+
+        let joints = world["joint"].as_array().unwrap_or(&empty);
+        for (i, joint) in joints.iter().enumerate() {
+            warnings.push(format!(
+                "{}: {} joints are not supported, skipped",
+                describe(joint, "joint", i),
+                joint["type"].as_str().unwrap_or("unknown")
+            ));
+        }
+        let images = world["image"].as_array().unwrap_or(&empty);
+        if !images.is_empty() {
+            warnings.push(format!(
+                "images are not supported, {} skipped",
+                images.len()
+            ));
+        }
+
+        Ok(Self { level, warnings })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LevelError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // Fixtures of static bodies become bodies of their own, which is exact. Dynamic bodies with
+    // several fixtures become one compound, with circles approximated by polygons.
+    fn import_body(value: &Value, name: String, level: &mut Level, warnings: &mut Vec<String>) {
+        let mut body = Reader {
+            value,
+            name,
+            warnings,
+        };
+        let position = flip(body.vector("position"));
+        let angle = -body.float("angle", 0.0);
+        let velocity = flip(body.vector("linearVelocity"));
+        let angular_velocity = -body.float("angularVelocity", 0.0);
+        let fixed_rotation = body.bool("fixedRotation");
+        let is_static = match body.int("type", STATIC_BODY) {
+            STATIC_BODY => true,
+            KINEMATIC_BODY => {
+                body.warn("kinematic bodies are not supported, imported as static");
+                true
+            }
+            _ => false,
+        };
+        if body.bool("bullet") {
+            body.warn("continuous collision is not supported, imported as a normal body");
+        }
+        if body.float("linearDamping", 0.0) != 0.0 {
+            body.warn("linear damping is not supported, ignored");
+        }
+        if body.float("angularDamping", 0.0) != 0.0 {
+            body.warn("angular damping is not supported, ignored");
+        }
+        if body.float("gravityScale", 1.0) != 1.0 {
+            body.warn("gravity scale is not supported, ignored");
+        }
+        if body.value["awake"].as_bool() == Some(false) {
+            body.warn("sleeping is not supported, imported awake");
+        }
+
+        let empty = Vec::new();
+        let fixtures = value["fixture"].as_array().unwrap_or(&empty);
+        if fixtures.is_empty() {
+            body.warn("no fixtures, skipped");
+            return;
+        }
+        let mut defs = Vec::new();
+        for (j, fixture) in fixtures.iter().enumerate() {
+            let mut fixture = Reader {
+                value: fixture,
+                name: format!("{}, {}", body.name, describe(fixture, "fixture", j)),
+                warnings: body.warnings,
+            };
+            let (shape, offset) = match Self::import_shape(&mut fixture, angle, is_static) {
+                Some(shape) => shape,
+                None => continue,
+            };
+            let mut density = fixture.float("density", 1.0);
+            if density <= 0.0 {
+                if !is_static {
+                    fixture.warn("density must be positive, using 1");
+                }
+                density = 1.0;
+            }
+            let friction = fixture.float("friction", 0.2);
+            let restitution = fixture.float("restitution", 0.0);
+            let filter = Filter {
+                category_bits: fixture.int("filter-categoryBits", 0x0001) as u16,
+                mask_bits: fixture.int("filter-maskBits", 0xFFFF) as u16,
+                group_index: fixture.int("filter-groupIndex", 0) as i16,
+            };
+            let is_sensor = fixture.bool("sensor");
+
+            // Circles turn with the body, the vertices of the other shapes are already rotated
+            let orient = match shape {
+                Shape::Circle { .. } => angle,
+                _ => 0.0,
+            };
+            defs.push(BodyDef {
+                shape,
+                position: (position + offset).to_real(),
+                orient: real(orient),
+                velocity: velocity.to_real(),
+                angular_velocity: real(angular_velocity),
+                is_static,
+                density: real(density),
+                fixed_rotation,
+                // Box2D mixes friction by geometric mean and restitution by max
+                material: Material::new("rube", real(friction), real(friction), real(restitution))
+                    .with_mixing(MixingRule::GeometricMean, MixingRule::Max),
+                filter,
+                is_sensor,
+                ..BodyDef::default()
+            });
+        }
+        if is_static {
+            level.bodies.extend(defs);
+            return;
+        }
+
+        // Chains are static, so they stay bodies of their own
+        let (chains, mut solids): (Vec<_>, Vec<_>) = defs
+            .into_iter()
+            .partition(|def| matches!(def.shape, Shape::Chain { .. }));
+        level.bodies.extend(chains);
+        let mut def = match solids.len() {
+            0 => return,
+            1 => solids.pop().unwrap(),
+            _ => Self::combine(&mut body, position.to_real(), solids),
+        };
+        if let Some(data) = Self::mass_data(&mut body, position, angle, &def) {
+            def.mass_data = Some(data);
+        }
+        level.bodies.push(def);
+    }
+
+    // One compound from the fixtures of a dynamic body, which takes everything but the shape
+    // from the first fixture
+    fn combine(body: &mut Reader, position: Vector2d<Real>, defs: Vec<BodyDef>) -> BodyDef {
+        let k_circle_sides = 16;
+        let mut pieces = Vec::new();
+        for def in &defs {
+            // Relative to the body's position, circles are the only fixtures that are not there
+            let offset = def.position - position;
+            match def.shape {
+                Shape::Circle { radius } => {
+                    let inc = real(2.0 * PI / k_circle_sides as f64);
+                    pieces.push(
+                        (0..k_circle_sides)
+                            .map(|i| {
+                                offset
+                                    + Vector2d::new(radius, real(0.0)).rotate(inc * real(i as f64))
+                            })
+                            .collect(),
+                    );
+                }
+                Shape::Polygon { ref vertices } => pieces.push(vertices.clone()),
+                _ => {}
+            }
+        }
+        if defs
+            .iter()
+            .any(|def| matches!(def.shape, Shape::Circle { .. }))
+        {
+            body.warn("circles of bodies with several fixtures are approximated by polygons");
+        }
+        let first = &defs[0];
+        // Custom mass data replaces the densities anyway
+        let custom_mass = !body.value["massData-mass"].is_null();
+        if !custom_mass && defs.iter().any(|def| def.density != first.density) {
+            body.warn("fixtures have different densities, using the first one's");
+        }
+        if defs.iter().any(|def| {
+            def.material != first.material
+                || def.filter != first.filter
+                || def.is_sensor != first.is_sensor
+        }) {
+            body.warn(
+                "fixtures have different materials, filters or sensor flags, using the first one's",
+            );
+        }
+        BodyDef {
+            shape: Shape::Compound {
+                vertices: Vec::new(),
+                pieces,
+            },
+            position,
+            orient: real(0.0),
+            ..first.clone()
+        }
+    }
+
+    // Box2D's mass data of a dynamic body, whose inertia is about the body's origin
+    fn mass_data(
+        body: &mut Reader,
+        position: Vector2d<f64>,
+        angle: f64,
+        def: &BodyDef,
+    ) -> Option<MassData> {
+        if body.value["massData-mass"].is_null() {
+            return None;
+        }
+        let mass = body.float("massData-mass", 0.0);
+        let center = body.vector("massData-center");
+        let inertia = body.float("massData-I", 0.0) - mass * center.len_square();
+        if mass <= 0.0 || inertia <= 0.0 {
+            body.warn("custom mass is not positive, computed from the density instead");
+            return None;
+        }
+        // Relative to the body's position in the level, in the body's turned space
+        let origin = def.position.to_f64() - position;
+        let center = (flip(center.rotate(-angle)) - origin).rotate(-def.orient.to_f64());
+        Some(MassData {
+            mass: real(mass),
+            center: center.to_real(),
+            inertia: real(inertia),
+        })
+    }
+
+    // The shape and its offset from the body's position in world space
+    fn import_shape(
+        fixture: &mut Reader,
+        angle: f64,
+        is_static: bool,
+    ) -> Option<(Shape, Vector2d<f64>)> {
+        let value = fixture.value;
+        // Body space with y up to world space with y down, around the body's position
+        let to_world = |v: Vector2d<f64>| flip(v.rotate(-angle));
+        if !value["circle"].is_null() {
+            let radius = float(&value["circle"]["radius"]).unwrap_or(0.0);
+            if radius <= 0.0 {
+                fixture.warn("circle without a positive radius, skipped");
+                return None;
+            }
+            let center = vector(&value["circle"]["center"]).unwrap_or_else(Vector2d::zero);
+            return Some((
+                Shape::Circle {
+                    radius: real(radius),
+                },
+                to_world(center),
+            ));
+        }
+        if !value["polygon"].is_null() {
+            let vertices = match vertices(&value["polygon"]["vertices"]) {
+                Some(vertices) => vertices,
+                None => {
+                    fixture.warn("polygon without vertices, skipped");
+                    return None;
+                }
+            };
+            // Flipping y reverses the winding
+            let vertices: Vec<_> = vertices
+                .into_iter()
+                .rev()
+                .map(|v| to_world(v).to_real())
+                .collect();
+            if let Err(e) = validate_convex(&vertices) {
+                fixture.warn(&format!("{}, skipped", e));
+                return None;
+            }
+            return Some((Shape::Polygon { vertices }, Vector2d::zero()));
+        }
+        if !value["chain"].is_null() {
+            let chain = &value["chain"];
+            let mut vertices = match vertices(&chain["vertices"]) {
+                Some(ref vertices) if vertices.len() >= 2 => vertices.clone(),
+                _ => {
+                    fixture.warn("chain with less than 2 vertices, skipped");
+                    return None;
+                }
+            };
+            if !is_static {
+                fixture.warn("chains are always static in this engine");
+            }
+            let is_loop = vertices.len() > 3 && vertices.first() == vertices.last();
+            if is_loop {
+                vertices.pop();
+            } else if chain["hasPrevVertex"].as_bool().unwrap_or(false)
+                || chain["hasNextVertex"].as_bool().unwrap_or(false)
+            {
+                fixture.warn("ghost vertices at the ends of a chain are not supported");
+            }
+            let shape = Shape::Chain {
+                vertices: vertices
+                    .into_iter()
+                    .map(|v| to_world(v).to_real())
+                    .collect(),
+                is_loop,
+            };
+            return Some((shape, Vector2d::zero()));
+        }
+        fixture.warn("no circle, polygon or chain shape, skipped");
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    const BOX_A: &str = r#"{ "polygon": { "vertices": {
+        "x": [-1, 0, 0, -1], "y": [-0.5, -0.5, 0.5, 0.5] } } }"#;
+    const BOX_B: &str = r#"{ "polygon": { "vertices": {
+        "x": [0, 1, 1, 0], "y": [-0.5, -0.5, 0.5, 0.5] } } }"#;
+    const CIRCLE: &str = r#"{ "circle": { "center": { "x": 0, "y": 1 }, "radius": 0.5 } }"#;
+
+    fn scene(body: &str) -> RubeImport {
+        RubeImport::from_json(&format!(r#"{{ "gravity": 0, "body": [{}] }}"#, body)).unwrap()
+    }
+
+    fn close(a: Vector2d<Real>, b: Vector2d<f64>) -> bool {
+        (a.to_f64() - b).len() < 1.0e-6
+    }
+
+    #[test]
+    fn fixtures_of_a_dynamic_body_become_one_compound() {
+        let import = scene(&format!(
+            r#"{{ "type": 2, "position": {{ "x": 4, "y": -5 }}, "angle": 0.5,
+                "fixture": [{}, {}, {}] }}"#,
+            BOX_A, BOX_B, CIRCLE
+        ));
+        assert_eq!(import.level.bodies.len(), 1);
+        match import.level.bodies[0].shape {
+            Shape::Compound {
+                ref vertices,
+                ref pieces,
+            } => {
+                assert!(vertices.is_empty());
+                assert_eq!(pieces.len(), 3);
+            }
+            _ => panic!("not a compound"),
+        }
+        assert_eq!(import.warnings.len(), 1);
+
+        let scene = Scene::from_level(&import.level).unwrap();
+        let object = scene.bodies()[0].object();
+        let object = object.borrow();
+        // The circle's weight pulls the center of mass up, which is down on the canvas
+        let center = object.world_center() - Vector2d::new(4.0, 5.0).to_real();
+        assert!(center.rotate(real(0.5)).y < real(0.0));
+    }
+
+    #[test]
+    fn mass_data_is_applied() {
+        for fixtures in &[format!("{}, {}", BOX_A, BOX_B), CIRCLE.to_string()] {
+            let import = scene(&format!(
+                r#"{{ "type": 2, "position": {{ "x": 4, "y": -5 }}, "angle": 0.5,
+                    "massData-mass": 2, "massData-center": {{ "x": 0.5, "y": 0.25 }},
+                    "massData-I": 3, "fixture": [{}] }}"#,
+                fixtures
+            ));
+            assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+            let scene = Scene::from_level(&import.level).unwrap();
+            let object = scene.bodies()[0].object();
+            let object = object.borrow();
+            assert_eq!(object.mass, real(2.0));
+            assert!((object.inertia.to_f64() - (3.0 - 2.0 * 0.3125)).abs() < 1.0e-6);
+            // Box2D's center in body space with y up, turned by the angle and flipped
+            let center = Vector2d::new(0.5, 0.25).rotate(0.5);
+            assert!(close(
+                object.world_center(),
+                Vector2d::new(4.0 + center.x, 5.0 - center.y)
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_mass_data_falls_back_to_the_density() {
+        let import = scene(&format!(
+            r#"{{ "type": 2, "massData-mass": 2, "massData-I": 0, "fixture": [{}] }}"#,
+            BOX_A
+        ));
+        assert_eq!(import.warnings.len(), 1);
+        assert!(import.level.bodies[0].mass_data.is_none());
+    }
+
+    #[test]
+    fn unsupported_body_settings_are_reported() {
+        let defaults = scene(&format!(
+            r#"{{ "type": 2, "linearDamping": 0, "angularDamping": 0, "gravityScale": 1,
+                "awake": true, "fixture": [{}] }}"#,
+            BOX_A
+        ));
+        assert!(defaults.warnings.is_empty(), "{:?}", defaults.warnings);
+
+        let import = scene(&format!(
+            r#"{{ "type": 2, "linearDamping": 0.5, "angularDamping": 0.1, "gravityScale": 0,
+                "awake": false, "fixture": [{}] }}"#,
+            BOX_A
+        ));
+        assert_eq!(import.level.bodies.len(), 1);
+        let warnings = import.warnings.join("\n");
+        for setting in &[
+            "linear damping",
+            "angular damping",
+            "gravity scale",
+            "sleeping",
+        ] {
+            assert!(warnings.contains(setting), "{}", warnings);
+        }
+        assert_eq!(import.warnings.len(), 4);
+    }
+}
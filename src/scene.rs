@@ -1,15 +1,33 @@
 use crate::body::*;
+use crate::level::*;
 use crate::manifold::*;
+use crate::material::*;
 use crate::math::*;
 use crate::rand::*;
+use crate::raster::*;
+use crate::render::*;
+use crate::svg::*;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+#[cfg(target_arch = "wasm32")]
 use stdweb::traits::*;
+#[cfg(target_arch = "wasm32")]
 use stdweb::unstable::TryInto;
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::html_element::CanvasElement;
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::{document, CanvasRenderingContext2d};
 
+// The page's canvas only exists on the web targets, everywhere else scenes are headless
 pub struct Canvas {
-    pub canvas: CanvasElement,
-    pub context: CanvasRenderingContext2d,
+    // The page's canvas and its context, None for headless scenes
+    #[cfg(target_arch = "wasm32")]
+    element: Option<(CanvasElement, CanvasRenderingContext2d)>,
     pub scaled_width: f64,
     pub scaled_height: f64,
 
@@ -18,6 +36,7 @@ pub struct Canvas {
 }
 
 impl Canvas {
+    #[cfg(target_arch = "wasm32")]
     pub fn new(attr_id: &str, width: f64, height: f64) -> Self {
         let document = document();
         let canvas: CanvasElement = document
@@ -30,142 +49,646 @@ impl Canvas {
         let scaled_width = canvas.width() as f64 / width;
         let scaled_height = canvas.height() as f64 / height;
         Self {
-            canvas,
-            context,
+            element: Some((canvas, context)),
             scaled_width,
             scaled_height,
             width,
             height,
         }
     }
+    // Not attached to a page, so it also works outside the browser. Positions given to the
+    // scene are in world units.
+    pub fn headless(width: f64, height: f64) -> Self {
+        Self {
+            #[cfg(target_arch = "wasm32")]
+            element: None,
+            scaled_width: 1.0,
+            scaled_height: 1.0,
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+// The inherent methods are called by path, plain method calls would find these ones first
+impl Context2d for CanvasRenderingContext2d {
+    fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        CanvasRenderingContext2d::clear_rect(self, x, y, width, height);
+    }
+    fn set_font(&mut self, font: &str) {
+        CanvasRenderingContext2d::set_font(self, font);
+    }
+    fn set_fill_style_color(&mut self, color: &str) {
+        CanvasRenderingContext2d::set_fill_style_color(self, color);
+    }
+    fn set_stroke_style_color(&mut self, color: &str) {
+        CanvasRenderingContext2d::set_stroke_style_color(self, color);
+    }
+    fn fill_text(&mut self, text: &str, x: f64, y: f64, max_width: Option<f64>) {
+        CanvasRenderingContext2d::fill_text(self, text, x, y, max_width);
+    }
+    fn begin_path(&mut self) {
+        CanvasRenderingContext2d::begin_path(self);
+    }
+    fn close_path(&mut self) {
+        CanvasRenderingContext2d::close_path(self);
+    }
+    fn move_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::move_to(self, x, y);
+    }
+    fn line_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::line_to(self, x, y);
+    }
+    fn stroke(&mut self) {
+        CanvasRenderingContext2d::stroke(self);
+    }
+    fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        CanvasRenderingContext2d::fill_rect(self, x, y, width, height);
+    }
+}
+
+// Decides whether two bodies collide at all, checked before the narrow phase
+pub trait ContactFilter {
+    fn should_collide(&self, a: &Object, b: &Object) -> bool {
+        a.filter.should_collide(&b.filter)
+    }
+}
+
+// Receives overlaps between sensors and other bodies, `sensor` is the first of the two that is
+// a sensor
+pub trait SensorListener {
+    fn sensor_enter(&mut self, _sensor: &Rc<RefCell<Object>>, _other: &Rc<RefCell<Object>>) {}
+    fn sensor_stay(&mut self, _sensor: &Rc<RefCell<Object>>, _other: &Rc<RefCell<Object>>) {}
+    fn sensor_exit(&mut self, _sensor: &Rc<RefCell<Object>>, _other: &Rc<RefCell<Object>>) {}
+}
+
+// Receives the life cycle of contacts between bodies that are not sensors
+pub trait ContactListener {
+    // The two bodies started touching, called with their first manifold
+    fn begin_contact(&mut self, _contact: &Manifold) {}
+    fn end_contact(&mut self, _a: &Rc<RefCell<Object>>, _b: &Rc<RefCell<Object>>) {}
+    // Called before solving every manifold, the contact can be disabled or have its friction
+    // and restitution changed for this step
+    fn pre_solve(&mut self, _contact: &mut Manifold) {}
+    // Called after solving every manifold, with the impulses that were applied
+    fn post_solve(&mut self, _contact: &Manifold) {}
+}
+
+#[cfg(target_arch = "wasm32")]
+fn render_string(frame: &mut Frame, text: &str, x: f64, y: f64, max_width: f64) {
+    frame.context.set_font("20px sans-serif");
+    frame.context.set_fill_style_color("black");
+    frame.context.fill_text(
+        text,
+        x * frame.scaled_width,
+        y * frame.scaled_height,
+        Some(max_width * frame.scaled_width),
+    );
+}
+
+// The mouse controls of the browser demo
+#[cfg(target_arch = "wasm32")]
+fn draw_help(frame: &mut Frame) {
+    render_string(frame, "Left click to spawn a polygon.", 0.5, 1.0, 9.0);
+    render_string(frame, "Right click to spawn a circle.", 0.5, 2.0, 9.0);
+    render_string(frame, "Middle click to spawn a capsule.", 0.5, 3.0, 9.0);
+}
+
+type ObjectPair = (Rc<RefCell<Object>>, Rc<RefCell<Object>>);
+
+fn contains_pair(pairs: &[ObjectPair], pair: &ObjectPair) -> bool {
+    pairs
+        .iter()
+        .any(|p| Rc::ptr_eq(&p.0, &pair.0) && Rc::ptr_eq(&p.1, &pair.1))
+}
+
+// Everything a step changes, so the scene can be rewound to it. Settings like materials and
+// masses are not part of it, and neither are the contacts, which every step finds anew.
+#[derive(Clone)]
+pub struct Snapshot {
+    bodies: Vec<(Rc<RefCell<Object>>, ObjectState)>,
+    sensor_overlaps: Vec<ObjectPair>,
+    touching: Vec<ObjectPair>,
+    passing_one_way: Vec<ObjectPair>,
+    rng: Rng,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestoreError {
+    // The body at this index of the snapshot is not the one of the scene
+    UnknownBody(usize),
 }
 
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestoreError::UnknownBody(index) => write!(
+                f,
+                "body {} of the snapshot is not in this scene, it was taken of another scene",
+                index
+            ),
+        }
+    }
+}
+
+impl Error for RestoreError {}
+
 pub struct Scene {
     canvas: Canvas,
-    m_dt: f64,
+    gravity: Vector2d<Real>,
+    m_dt: Real,
     m_iterations: u32,
+    restitution_threshold: Real,
+    position_iterations: u32,
+    linear_slop: Real,
+    correction_rate: Real,
     bodies: Vec<Box<dyn RigidBody>>,
     contacts: Vec<Manifold>,
+    contact_filter: Option<Box<dyn ContactFilter>>,
+    materials: MaterialTable,
+    sensor_overlaps: Vec<ObjectPair>,
+    sensor_listener: Option<Box<dyn SensorListener>>,
+    touching: Vec<ObjectPair>,
+    passing_one_way: Vec<ObjectPair>,
+    contact_listener: Option<Box<dyn ContactListener>>,
     rng: Rng,
 }
 
 impl Scene {
+    #[cfg(target_arch = "wasm32")]
     pub fn canvas(&self) -> &CanvasElement {
-        &self.canvas.canvas
+        &self
+            .canvas
+            .element
+            .as_ref()
+            .expect("a headless scene has no canvas")
+            .0
+    }
+    // Draw into the canvas matching `attr_id` from now on
+    #[cfg(target_arch = "wasm32")]
+    pub fn attach_canvas(&mut self, attr_id: &str) {
+        self.canvas = Canvas::new(attr_id, self.canvas.width, self.canvas.height);
     }
     pub fn resize(&mut self, height: u32, width: u32) {
         let h = ::std::cmp::min(800, height);
         let w = ::std::cmp::min(800, width);
-        self.canvas.canvas.set_height(h);
-        self.canvas.canvas.set_width(w);
+        #[cfg(target_arch = "wasm32")]
+        if let Some((ref canvas, _)) = self.canvas.element {
+            canvas.set_height(h);
+            canvas.set_width(w);
+        }
         self.canvas.scaled_width = w as f64 / self.canvas.width;
         self.canvas.scaled_height = h as f64 / self.canvas.height;
     }
+    // A scene seeded from the operating system, `seed()` tells how to reproduce it
     pub fn new() -> Scene {
+        Self::with_rng(Rng::new())
+    }
+    // The same seed always gives the same shapes and colors
+    pub fn with_seed(seed: u64) -> Scene {
+        Self::with_rng(Rng::with_seed(seed))
+    }
+    fn with_rng(rng: Rng) -> Scene {
+        #[cfg(target_arch = "wasm32")]
         let canvas = Canvas::new("#canvas", 20.0, 20.0);
-        let mut bodies = Vec::new();
-        let mut fixed_circle = Circle::new(10.0, 10.0, 1.0);
+        #[cfg(not(target_arch = "wasm32"))]
+        let canvas = Canvas::headless(20.0, 20.0);
+        let mut scene = Self::empty(rng, canvas);
+
+        let mut fixed_circle = Circle::new(real(10.0), real(10.0), real(1.0));
         fixed_circle.set_static();
-        bodies.push(Box::new(fixed_circle) as Box<dyn RigidBody>);
-
-        let mut fixed_rectangle = Polygon::new(10.0, 17.0, 18.0);
-        let top_right = Vector2d::new(9.0, -0.5);
-        let top_left = Vector2d::new(-9.0, -0.5);
-        let bottom_left = Vector2d::new(-9.0, 0.5);
-        let bottom_right = Vector2d::new(9.0, 0.5);
-        let rectangle_vertices = vec![top_left, top_right, bottom_right, bottom_left];
-        fixed_rectangle.set_vertices(&rectangle_vertices);
-        fixed_rectangle.set_static();
-        bodies.push(Box::new(fixed_rectangle) as Box<dyn RigidBody>);
+        scene.add_body(Box::new(fixed_circle));
 
+        let mut fixed_rectangle = Polygon::new_box(
+            Vector2d::new(10.0, 17.0).to_real(),
+            real(9.0),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        fixed_rectangle.set_static();
+        // Wood resists rolling, so circles on the floor come to rest
+        fixed_rectangle.object.borrow_mut().material = Material::wood();
+        scene.add_body(Box::new(fixed_rectangle));
+        scene
+    }
+    fn empty(rng: Rng, canvas: Canvas) -> Scene {
         Scene {
             canvas: canvas,
-            m_dt: 1.0 / 60.0,
+            gravity: Vector2d::new(real(0.0), real(9.8)),
+            m_dt: real(1.0 / 60.0),
             m_iterations: 10,
-            bodies: bodies,
+            restitution_threshold: real(1.0),
+            position_iterations: 4,
+            linear_slop: real(0.005),
+            correction_rate: real(0.4),
+            bodies: Vec::new(),
             contacts: Vec::new(),
-            rng: Rng::new(),
+            contact_filter: None,
+            materials: MaterialTable::new(),
+            sensor_overlaps: Vec::new(),
+            sensor_listener: None,
+            touching: Vec::new(),
+            passing_one_way: Vec::new(),
+            contact_listener: None,
+            rng,
+        }
+    }
+    // A headless scene holding only what the level describes, `attach_canvas` shows it on a
+    // page
+    pub fn from_level(level: &Level) -> Result<Scene, LevelError> {
+        level.validate()?;
+        let rng = match (level.seed, level.rng_state) {
+            (Some(seed), Some(state)) => Rng::resume(seed, state),
+            (Some(seed), None) => Rng::with_seed(seed),
+            (None, _) => Rng::new(),
+        };
+        let mut scene = Self::empty(rng, Canvas::headless(20.0, 20.0));
+        scene.gravity = level.gravity;
+        scene.m_dt = level.timestep;
+        scene.m_iterations = level.iterations;
+        scene.position_iterations = level.position_iterations;
+        scene.linear_slop = level.linear_slop;
+        scene.correction_rate = level.correction_rate;
+        scene.restitution_threshold = level.restitution_threshold;
+        scene.materials = level.materials.clone();
+        for (i, def) in level.bodies.iter().enumerate() {
+            let body = def.build().map_err(|e| LevelError::InvalidBody(i, e))?;
+            if def.color.is_some() {
+                scene.bodies.push(body);
+            } else {
+                scene.add_body(body);
+            }
         }
+        Ok(scene)
+    }
+    // Body types that levels do not know are left out
+    pub fn to_level(&self) -> Level {
+        Level {
+            version: LEVEL_VERSION,
+            gravity: self.gravity,
+            timestep: self.m_dt,
+            iterations: self.m_iterations,
+            position_iterations: self.position_iterations,
+            linear_slop: self.linear_slop,
+            correction_rate: self.correction_rate,
+            restitution_threshold: self.restitution_threshold,
+            seed: Some(self.rng.seed()),
+            rng_state: Some(self.rng.state()),
+            materials: self.materials.clone(),
+            bodies: self
+                .bodies
+                .iter()
+                .filter_map(|body| BodyDef::from_body(body.as_ref()))
+                .collect(),
+        }
+    }
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+    pub fn bodies(&self) -> &[Box<dyn RigidBody>] {
+        &self.bodies
+    }
+    pub fn contacts(&self) -> &[Manifold] {
+        &self.contacts
+    }
+    // Add a body colored by the scene's random generator
+    pub fn add_body(&mut self, body: Box<dyn RigidBody>) {
+        body.object().borrow_mut().color = Color::random(&mut self.rng);
+        self.bodies.push(body);
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            bodies: self
+                .bodies
+                .iter()
+                .map(|body| {
+                    let object = body.object();
+                    let state = object.borrow().state();
+                    (object, state)
+                })
+                .collect(),
+            sensor_overlaps: self.sensor_overlaps.clone(),
+            touching: self.touching.clone(),
+            passing_one_way: self.passing_one_way.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+    // Rewind to a snapshot of this scene, bodies added since it was taken are removed. Nothing
+    // changes if the snapshot was taken of another scene.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), RestoreError> {
+        for (i, (object, _)) in snapshot.bodies.iter().enumerate() {
+            match self.bodies.get(i) {
+                Some(body) if Rc::ptr_eq(&body.object(), object) => {}
+                _ => return Err(RestoreError::UnknownBody(i)),
+            }
+        }
+        self.bodies.truncate(snapshot.bodies.len());
+        for (object, state) in &snapshot.bodies {
+            object.borrow_mut().set_state(state);
+        }
+        self.contacts.clear();
+        self.sensor_overlaps = snapshot.sensor_overlaps.clone();
+        self.touching = snapshot.touching.clone();
+        self.passing_one_way = snapshot.passing_one_way.clone();
+        self.rng = snapshot.rng.clone();
+        Ok(())
+    }
+    pub fn set_gravity(&mut self, gravity: Vector2d<Real>) {
+        self.gravity = gravity;
+    }
+    pub fn timestep(&self) -> Real {
+        self.m_dt
+    }
+    pub fn set_timestep(&mut self, dt: Real) {
+        self.m_dt = dt;
+    }
+    // Velocity iterations per step
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.m_iterations = iterations;
+    }
+    // Contacts approaching slower than this do not bounce
+    pub fn set_restitution_threshold(&mut self, threshold: Real) {
+        self.restitution_threshold = threshold;
+    }
+    // Up to `iterations` passes push overlapping bodies apart, each correcting `rate` of the
+    // overlap beyond `slop`
+    pub fn set_position_correction(&mut self, iterations: u32, slop: Real, rate: Real) {
+        self.position_iterations = iterations;
+        self.linear_slop = slop;
+        self.correction_rate = rate;
+    }
+    pub fn set_contact_filter(&mut self, filter: Box<dyn ContactFilter>) {
+        self.contact_filter = Some(filter);
+    }
+    // Material and material pair overrides, applied to every new contact
+    pub fn materials_mut(&mut self) -> &mut MaterialTable {
+        &mut self.materials
+    }
+    pub fn set_sensor_listener(&mut self, listener: Box<dyn SensorListener>) {
+        self.sensor_listener = Some(listener);
+    }
+    pub fn set_contact_listener(&mut self, listener: Box<dyn ContactListener>) {
+        self.contact_listener = Some(listener);
     }
     pub fn add_circle(&mut self, x: f64, y: f64) {
         let c = Circle::new(
-            x / self.canvas.scaled_width,
-            y / self.canvas.scaled_height,
+            real(x / self.canvas.scaled_width),
+            real(y / self.canvas.scaled_height),
             // Random float from 0.3~0.8
-            self.rng.gen_range(0, 10000) as f64 / 20000.0 + 0.3,
+            real(self.rng.gen_range(0, 10000) as f64 / 20000.0 + 0.3),
         );
-        self.bodies.push(Box::new(c) as Box<dyn RigidBody>);
+        self.add_body(Box::new(c));
     }
     pub fn add_polygon(&mut self, x: f64, y: f64) {
         let p = Polygon::new(
-            x / self.canvas.scaled_width,
-            y / self.canvas.scaled_height,
+            real(x / self.canvas.scaled_width),
+            real(y / self.canvas.scaled_height),
             // Random float from 0.5~1.5
-            self.rng.gen_range(0, 10000) as f64 / 10000.0 + 0.5,
+            real(self.rng.gen_range(0, 10000) as f64 / 10000.0 + 0.5),
+            &mut self.rng,
         );
-        self.bodies.push(Box::new(p));
-    }
-    fn render_string(&mut self, text: &str, x: f64, y: f64, max_width: f64) {
-        self.canvas.context.set_font("20px sans-serif");
-        self.canvas.context.set_fill_style_color("black");
-        self.canvas.context.fill_text(
-            text,
-            x * self.canvas.scaled_width,
-            y * self.canvas.scaled_height,
-            Some(max_width * self.canvas.scaled_width),
+        self.add_body(Box::new(p));
+    }
+    pub fn add_capsule(&mut self, x: f64, y: f64) {
+        let c = Capsule::new(
+            real(x / self.canvas.scaled_width),
+            real(y / self.canvas.scaled_height),
+            // Random float from 0.3~0.8
+            real(self.rng.gen_range(0, 10000) as f64 / 20000.0 + 0.3),
+            // Random float from 0.2~0.4
+            real(self.rng.gen_range(0, 10000) as f64 / 50000.0 + 0.2),
         );
+        c.object.borrow_mut().orient = real(self.rng.gen_range(0, 10000) as f64 / 10000.0 * PI);
+        self.add_body(Box::new(c));
     }
+    // Draw into the page's canvas, if there is one
+    #[cfg(target_arch = "wasm32")]
     pub fn render(&mut self) {
+        let (canvas, mut context) = match self.canvas.element {
+            Some((ref canvas, ref context)) => (canvas, context.clone()),
+            None => return,
+        };
+        let mut frame = Frame {
+            context: &mut context,
+            scaled_width: self.canvas.scaled_width,
+            scaled_height: self.canvas.scaled_height,
+            width: canvas.width() as f64,
+            height: canvas.height() as f64,
+        };
+        self.draw(&mut frame);
+        draw_help(&mut frame);
+    }
+    // A standalone SVG document of the current state, `width` and `height` are in pixels
+    pub fn render_svg(&self, width: f64, height: f64) -> String {
+        let mut context = SvgContext::new();
+        self.draw(&mut Frame {
+            context: &mut context,
+            scaled_width: width / self.canvas.width,
+            scaled_height: height / self.canvas.height,
+            width,
+            height,
+        });
+        context.finish(width, height)
+    }
+    // The current state drawn on the CPU, `width` and `height` are in pixels
+    pub fn render_raster(&self, width: u32, height: u32) -> Raster {
+        let mut raster = Raster::new(width, height);
+        self.draw(&mut Frame {
+            context: &mut raster,
+            scaled_width: f64::from(width) / self.canvas.width,
+            scaled_height: f64::from(height) / self.canvas.height,
+            width: f64::from(width),
+            height: f64::from(height),
+        });
+        raster
+    }
+    // Run `steps` steps and save the frame after every `every`th one as a numbered PNG in
+    // `directory`, which is created if needed. Each step is taken by `advance`, which is given
+    // the step number from 1, so callers can time or log it, `|scene, _| Ok(scene.step())` just
+    // steps.
+    pub fn record_png_sequence<P, F>(
+        &mut self,
+        steps: u32,
+        every: u32,
+        width: u32,
+        height: u32,
+        directory: P,
+        mut advance: F,
+    ) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&mut Scene, u32) -> io::Result<()>,
+    {
+        if every == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frames have to be saved every 1 step or more",
+            ));
+        }
+        let directory = directory.as_ref();
+        let with_path = |path: &Path, e: io::Error| {
+            io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+        };
+        fs::create_dir_all(directory).map_err(|e| with_path(directory, e))?;
+        for step in 1..=steps {
+            advance(self, step)?;
+            if step % every == 0 {
+                let path = frame_path(directory, step / every - 1);
+                self.render_raster(width, height)
+                    .save_png(&path)
+                    .map_err(|e| with_path(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+    // Draw the bodies and contacts into any frame
+    pub fn draw(&self, frame: &mut Frame) {
         // Clear canvas
-        self.canvas.context.clear_rect(
-            0.0,
-            0.0,
-            self.canvas.canvas.width() as f64,
-            self.canvas.canvas.height() as f64,
-        );
-
-        // Draw texts
-        self.render_string("Left click to spawn a polygon.", 0.5, 1.0, 9.0);
-        self.render_string("Right click to spawn a circle.", 0.5, 2.0, 9.0);
+        frame
+            .context
+            .clear_rect(0.0, 0.0, frame.width, frame.height);
 
         // Draw rigid bodies
         for body in &self.bodies {
-            body.draw(&mut self.canvas);
+            body.draw(frame);
         }
 
         // Draw manifolds
         for manifold in &self.contacts {
-            manifold.draw(&mut self.canvas);
+            manifold.draw(frame);
         }
     }
+    // Bodies and contacts are kept in insertion order and visited in that order, so with the
+    // `deterministic` feature the same inputs give bit-identical results on every platform
     pub fn step(&mut self) {
         // Generate new collision info
         self.contacts.clear();
+        let mut overlaps = Vec::new();
+        let mut touching = Vec::new();
+        let mut first_manifolds = Vec::new();
         for (i, body_a) in self.bodies.iter().enumerate() {
             for body_b in self.bodies.iter().skip(i + 1) {
                 let object_a = body_a.object();
                 let object_b = body_b.object();
-                let r1 = body_a.radius() * self.canvas.scaled_width;
-                let r2 = body_b.radius() * self.canvas.scaled_width;
-                if (object_a.borrow().position - object_b.borrow().position).len_square() > r1 * r2
+                // Bounding circles in world units, so the result does not depend on the canvas
+                let radius_sum = body_a.radius() + body_b.radius();
+                if (object_a.borrow().position - object_b.borrow().position).len_square()
+                    > radius_sum * radius_sum
                 {
                     continue;
                 }
-                if object_a.borrow().inverse_mass == 0.0 && object_b.borrow().inverse_mass == 0.0 {
+                if object_a.borrow().inverse_mass == real(0.0)
+                    && object_b.borrow().inverse_mass == real(0.0)
+                {
                     continue;
                 }
-                if let Some(m) = Manifold::solve_collision(body_a, body_b) {
-                    self.contacts.push(m);
+                let should_collide = match self.contact_filter {
+                    Some(ref filter) => {
+                        filter.should_collide(&object_a.borrow(), &object_b.borrow())
+                    }
+                    None => object_a
+                        .borrow()
+                        .filter
+                        .should_collide(&object_b.borrow().filter),
+                };
+                if !should_collide {
+                    continue;
+                }
+                let manifolds = Manifold::solve_collision(body_a.as_ref(), body_b.as_ref());
+                if object_a.borrow().is_sensor || object_b.borrow().is_sensor {
+                    if !manifolds.is_empty() {
+                        let sensor_first = object_a.borrow().is_sensor;
+                        overlaps.push(if sensor_first {
+                            (object_a, object_b)
+                        } else {
+                            (object_b, object_a)
+                        });
+                    }
+                    continue;
+                }
+                if !manifolds.is_empty() {
+                    touching.push((object_a, object_b));
+                    first_manifolds.push(self.contacts.len());
+                    for mut manifold in manifolds {
+                        let material = self.materials.mix(
+                            &manifold.object_a().borrow().material,
+                            &manifold.object_b().borrow().material,
+                        );
+                        manifold.set_material(material);
+                        self.contacts.push(manifold);
+                    }
+                }
+            }
+        }
+
+        // Report sensor overlaps
+        if let Some(ref mut listener) = self.sensor_listener {
+            for pair in &self.sensor_overlaps {
+                if !contains_pair(&overlaps, pair) {
+                    listener.sensor_exit(&pair.0, &pair.1);
+                }
+            }
+            for pair in &overlaps {
+                if contains_pair(&self.sensor_overlaps, pair) {
+                    listener.sensor_stay(&pair.0, &pair.1);
+                } else {
+                    listener.sensor_enter(&pair.0, &pair.1);
+                }
+            }
+        }
+        self.sensor_overlaps = overlaps;
+
+        // One-way platforms keep letting a body through until they stop touching
+        let mut passing_one_way = Vec::new();
+        for (k, pair) in touching.iter().enumerate() {
+            if !pair.0.borrow().one_way && !pair.1.borrow().one_way {
+                continue;
+            }
+            let end = first_manifolds
+                .get(k + 1)
+                .cloned()
+                .unwrap_or(self.contacts.len());
+            let manifolds = &mut self.contacts[first_manifolds[k]..end];
+            if contains_pair(&self.passing_one_way, pair)
+                || manifolds.iter().any(|m| m.passes_one_way())
+            {
+                for manifold in manifolds {
+                    manifold.set_enabled(false);
                 }
+                passing_one_way.push((pair.0.clone(), pair.1.clone()));
             }
         }
+        self.passing_one_way = passing_one_way;
+
+        // Report contacts that began or ended since the last step
+        if let Some(ref mut listener) = self.contact_listener {
+            for pair in &self.touching {
+                if !contains_pair(&touching, pair) {
+                    listener.end_contact(&pair.0, &pair.1);
+                }
+            }
+            for (pair, &first) in touching.iter().zip(&first_manifolds) {
+                if !contains_pair(&self.touching, pair) {
+                    listener.begin_contact(&self.contacts[first]);
+                }
+            }
+            for contact in &mut self.contacts {
+                listener.pre_solve(contact);
+            }
+        }
+        self.touching = touching;
 
         // Integrate forces
         for body in &mut self.bodies {
-            body.integrate_forces(self.m_dt);
+            body.integrate_forces(self.gravity, self.m_dt);
         }
 
         // Initialize collision
+        for contact in &mut self.contacts {
+            contact.prepare(self.restitution_threshold);
+        }
         for _ in 0..self.m_iterations {
             for contact in &mut self.contacts {
                 contact.apply_impulse();
@@ -177,9 +700,616 @@ impl Scene {
             body.integrate_velocity(self.m_dt);
         }
 
-        // Correct positions
-        for contact in &mut self.contacts {
-            contact.position_correction();
+        // Correct positions, until no contact overlaps much more than the slop
+        for _ in 0..self.position_iterations {
+            let mut min_separation = real(0.0);
+            for contact in &mut self.contacts {
+                min_separation = min_separation
+                    .min(contact.solve_position(self.linear_slop, self.correction_rate));
+            }
+            if min_separation >= real(-1.5) * self.linear_slop {
+                break;
+            }
         }
+
+        if let Some(ref mut listener) = self.contact_listener {
+            for contact in &self.contacts {
+                listener.post_solve(contact);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Headless scenes take world coordinates
+    fn pile(seed: u64) -> Scene {
+        let mut scene = Scene::with_seed(seed);
+        for i in 0..4 {
+            let x = 6.0 + 2.0 * i as f64;
+            scene.add_polygon(x, 4.0);
+            scene.add_circle(x + 0.5, 1.5);
+            scene.add_capsule(x - 0.5, 7.0);
+        }
+        scene
+    }
+
+    // The exact bits of every position, orientation and velocity
+    fn state_bits(scene: &Scene) -> Vec<u64> {
+        scene
+            .bodies()
+            .iter()
+            .flat_map(|body| {
+                let object = body.object();
+                let object = object.borrow();
+                vec![
+                    object.position.x,
+                    object.position.y,
+                    object.orient,
+                    object.velocity.x,
+                    object.velocity.y,
+                    object.angular_velocity,
+                ]
+            })
+            .map(|value| value.to_f64().to_bits())
+            .collect()
+    }
+
+    fn run(scene: &mut Scene, steps: usize) -> Vec<Vec<u64>> {
+        (0..steps)
+            .map(|_| {
+                scene.step();
+                state_bits(scene)
+            })
+            .collect()
+    }
+
+    type Body = Rc<RefCell<Object>>;
+
+    // An empty scene with a static floor whose top is at y = 16.5
+    fn floor_scene() -> (Scene, Body) {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        let mut floor = Polygon::new_box(
+            Vector2d::new(10.0, 17.0).to_real(),
+            real(9.0),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        floor.set_static();
+        let object = floor.object.clone();
+        scene.add_body(Box::new(floor));
+        (scene, object)
+    }
+
+    // A unit box
+    fn add_box(scene: &mut Scene, x: f64, y: f64) -> Body {
+        let block = Polygon::new_box(
+            Vector2d::new(x, y).to_real(),
+            real(0.5),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        let object = block.object.clone();
+        scene.add_body(Box::new(block));
+        object
+    }
+
+    fn add_ball(scene: &mut Scene, x: f64, y: f64, radius: f64) -> Body {
+        let ball = Circle::new(real(x), real(y), real(radius));
+        let object = ball.object.clone();
+        scene.add_body(Box::new(ball));
+        object
+    }
+
+    fn filter(category_bits: u16, mask_bits: u16, group_index: i16) -> Filter {
+        Filter {
+            category_bits,
+            mask_bits,
+            group_index,
+        }
+    }
+
+    // Whether two overlapping boxes with these filters touch
+    fn boxes_collide(a: Filter, b: Filter, contact_filter: Option<Box<dyn ContactFilter>>) -> bool {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        scene.set_gravity(Vector2d::zero());
+        if let Some(contact_filter) = contact_filter {
+            scene.set_contact_filter(contact_filter);
+        }
+        add_box(&mut scene, 5.0, 5.0).borrow_mut().filter = a;
+        add_box(&mut scene, 5.5, 5.0).borrow_mut().filter = b;
+        scene.step();
+        !scene.contacts().is_empty()
+    }
+
+    #[test]
+    fn filters_decide_which_pairs_collide() {
+        let all = Filter::default();
+        for &(a, b, collide) in &[
+            (all, all, true),
+            // The same negative group never collides, whatever the masks
+            (filter(1, 0xFFFF, -1), filter(1, 0xFFFF, -1), false),
+            // The same positive group always does
+            (filter(1, 0, 2), filter(1, 0, 2), true),
+            // Different groups fall back to the masks
+            (filter(1, 0xFFFF, -1), filter(1, 0xFFFF, -2), true),
+            // Either mask can refuse the other category
+            (filter(2, 0xFFFF, 0), filter(1, !2, 0), false),
+            (filter(2, !1, 0), filter(1, 0xFFFF, 0), false),
+            (filter(2, 1, 0), filter(1, 2, 0), true),
+        ] {
+            assert_eq!(a.should_collide(&b), collide);
+            assert_eq!(b.should_collide(&a), collide);
+            assert_eq!(boxes_collide(a, b, None), collide);
+            assert_eq!(boxes_collide(b, a, None), collide);
+        }
+    }
+
+    #[test]
+    fn contact_filters_can_veto_pairs() {
+        // Bodies of the "ghost" material touch nothing, the others use their filters
+        struct NoGhosts;
+        impl ContactFilter for NoGhosts {
+            fn should_collide(&self, a: &Object, b: &Object) -> bool {
+                a.material.name != "ghost"
+                    && b.material.name != "ghost"
+                    && a.filter.should_collide(&b.filter)
+            }
+        }
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        scene.set_gravity(Vector2d::zero());
+        scene.set_contact_filter(Box::new(NoGhosts));
+        let ghost = add_box(&mut scene, 5.0, 5.0);
+        ghost.borrow_mut().material.name = "ghost".to_string();
+        add_box(&mut scene, 5.5, 5.0);
+        add_box(&mut scene, 10.0, 5.0);
+        add_box(&mut scene, 10.5, 5.0);
+        scene.step();
+        assert_eq!(scene.contacts().len(), 1);
+        let contact = &scene.contacts()[0];
+        assert!(!Rc::ptr_eq(contact.object_a(), &ghost));
+        assert!(!Rc::ptr_eq(contact.object_b(), &ghost));
+        // The filter replaces the default test entirely
+        let none = filter(1, 0, 0);
+        assert!(!boxes_collide(none, none, None));
+        assert!(!boxes_collide(none, none, Some(Box::new(NoGhosts))));
+    }
+
+    #[test]
+    fn players_pass_through_their_own_bullets() {
+        for &own in &[true, false] {
+            let mut scene = Scene::from_level(&Level::default()).unwrap();
+            scene.set_gravity(Vector2d::zero());
+            let player = add_box(&mut scene, 10.0, 5.0);
+            let wall = add_box(&mut scene, 13.0, 5.0);
+            let bullet = add_ball(&mut scene, 7.0, 5.2, 0.1);
+            bullet.borrow_mut().velocity = Vector2d::new(real(10.0), real(0.0));
+            if own {
+                player.borrow_mut().filter.group_index = -1;
+                bullet.borrow_mut().filter.group_index = -1;
+            }
+            // Until the bullet would reach the wall
+            for _ in 0..30 {
+                scene.step();
+            }
+            let hit = bullet.borrow().velocity.x.to_f64() != 10.0
+                || player.borrow().velocity.len_square() != real(0.0);
+            assert_eq!(hit, !own);
+            if own {
+                // Through the player, and other bodies still stop the bullet
+                assert!(bullet.borrow().position.x.to_f64() > 11.0);
+                for _ in 0..30 {
+                    scene.step();
+                }
+                assert!(bullet.borrow().position.x.to_f64() < 12.5);
+                assert!(wall.borrow().velocity.x > real(0.0));
+            }
+        }
+    }
+
+    // What a listener saw, shared with the test
+    type Events = Rc<RefCell<Vec<String>>>;
+
+    struct SensorLog(Events);
+
+    impl SensorListener for SensorLog {
+        fn sensor_enter(&mut self, _sensor: &Body, _other: &Body) {
+            self.0.borrow_mut().push("enter".to_string());
+        }
+        fn sensor_stay(&mut self, _sensor: &Body, _other: &Body) {
+            self.0.borrow_mut().push("stay".to_string());
+        }
+        fn sensor_exit(&mut self, _sensor: &Body, _other: &Body) {
+            self.0.borrow_mut().push("exit".to_string());
+        }
+    }
+
+    #[test]
+    fn sensors_report_enter_stay_exit_and_never_push() {
+        for &is_static in &[true, false] {
+            let mut scene = Scene::from_level(&Level::default()).unwrap();
+            scene.set_gravity(Vector2d::zero());
+            let events = Events::default();
+            scene.set_sensor_listener(Box::new(SensorLog(events.clone())));
+            let sensor = add_box(&mut scene, 10.0, 5.0);
+            sensor.borrow_mut().is_sensor = true;
+            if is_static {
+                sensor.borrow_mut().set_static();
+            }
+            let ball = add_ball(&mut scene, 8.0, 5.0, 0.25);
+            let velocity = Vector2d::new(real(3.0), real(0.0));
+            ball.borrow_mut().velocity = velocity;
+            for _ in 0..90 {
+                scene.step();
+                assert_eq!(ball.borrow().velocity, velocity);
+                assert_eq!(sensor.borrow().velocity, Vector2d::zero());
+                assert_eq!(ball.borrow().angular_velocity, real(0.0));
+                assert!(scene.contacts().is_empty());
+            }
+            // Through the sensor and out the other side
+            assert!(ball.borrow().position.x.to_f64() > 11.0);
+            let events = events.borrow();
+            let stays = events.iter().filter(|e| *e == "stay").count();
+            assert!(stays > 5);
+            assert_eq!(events.len(), stays + 2);
+            assert_eq!(events[0], "enter");
+            assert!(events[1..=stays].iter().all(|e| e == "stay"));
+            assert_eq!(events[stays + 1], "exit");
+        }
+    }
+
+    // Logs every call, and disables contacts in pre_solve when asked to
+    struct ContactLog {
+        events: Events,
+        disable: bool,
+    }
+
+    impl ContactListener for ContactLog {
+        fn begin_contact(&mut self, _contact: &Manifold) {
+            self.events.borrow_mut().push("begin".to_string());
+        }
+        fn end_contact(&mut self, _a: &Body, _b: &Body) {
+            self.events.borrow_mut().push("end".to_string());
+        }
+        fn pre_solve(&mut self, contact: &mut Manifold) {
+            if self.disable {
+                contact.set_enabled(false);
+            }
+            self.events.borrow_mut().push("pre".to_string());
+        }
+        fn post_solve(&mut self, contact: &Manifold) {
+            let impulse: Real = contact.normal_impulses().iter().sum();
+            self.events
+                .borrow_mut()
+                .push(format!("post {}", impulse.to_f64()));
+        }
+    }
+
+    // A ball moving right at `speed` towards a static box
+    fn ball_and_box(speed: f64, disable: bool) -> (Scene, Body, Events) {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        scene.set_gravity(Vector2d::zero());
+        let events = Events::default();
+        scene.set_contact_listener(Box::new(ContactLog {
+            events: events.clone(),
+            disable,
+        }));
+        add_box(&mut scene, 10.0, 5.0).borrow_mut().set_static();
+        let ball = add_ball(&mut scene, 8.5, 5.0, 0.5);
+        ball.borrow_mut().velocity = Vector2d::new(real(speed), real(0.0));
+        (scene, ball, events)
+    }
+
+    #[test]
+    fn contact_events_begin_solve_and_end_in_order() {
+        // Too slow to bounce, so the ball stays against the box until it is pulled away
+        let (mut scene, ball, events) = ball_and_box(0.8, false);
+        let steps: Vec<Vec<String>> = (0..90)
+            .map(|i| {
+                if i == 60 {
+                    ball.borrow_mut().velocity = Vector2d::new(real(-2.0), real(0.0));
+                }
+                scene.step();
+                events.borrow_mut().drain(..).collect()
+            })
+            .collect();
+        let first = steps.iter().position(|events| !events.is_empty()).unwrap();
+        let last = steps.iter().rposition(|events| !events.is_empty()).unwrap();
+        // It ends right after the pull, not before
+        assert!(first < 50 && last == 61, "{} {}", first, last);
+        for (i, events) in steps[first..=last].iter().enumerate() {
+            let mut events = &events[..];
+            if i == 0 {
+                assert_eq!(events[0], "begin");
+                events = &events[1..];
+            }
+            if first + i == last {
+                assert_eq!(events, ["end"]);
+                continue;
+            }
+            // Every manifold goes through pre_solve before any is solved
+            let pre = events.iter().take_while(|e| *e == "pre").count();
+            assert!(pre > 0, "{:?}", events);
+            assert_eq!(events.len(), 2 * pre, "{:?}", events);
+            assert!(events[pre..].iter().all(|e| e.starts_with("post")));
+        }
+        assert!(steps[first]
+            .iter()
+            .any(|e| e.starts_with("post") && e != "post 0"));
+    }
+
+    #[test]
+    fn contacts_disabled_in_pre_solve_apply_no_impulse() {
+        let (mut scene, ball, events) = ball_and_box(3.0, true);
+        for _ in 0..60 {
+            scene.step();
+        }
+        // Straight through the box
+        assert_eq!(ball.borrow().velocity, Vector2d::new(real(3.0), real(0.0)));
+        assert!(ball.borrow().position.x.to_f64() > 11.0);
+        let events = events.borrow();
+        assert_eq!(events.first().map(String::as_str), Some("begin"));
+        assert_eq!(events.last().map(String::as_str), Some("end"));
+        assert!(events.contains(&"pre".to_string()));
+        for event in events.iter().filter(|e| e.starts_with("post")) {
+            assert_eq!(event, "post 0");
+        }
+    }
+
+    // A static one-way platform from (7, 9.75) to (13, 10.25) and a ball of radius 0.3 at `y`
+    // moving down at `vy`
+    fn platform_and_ball(y: f64, vy: f64) -> (Scene, Body) {
+        let mut scene = Scene::from_level(&Level::default()).unwrap();
+        let mut platform = Polygon::new_box(
+            Vector2d::new(10.0, 10.0).to_real(),
+            real(3.0),
+            real(0.25),
+            real(0.0),
+        )
+        .unwrap();
+        platform.set_static();
+        platform.object.borrow_mut().one_way = true;
+        scene.add_body(Box::new(platform));
+        let ball = add_ball(&mut scene, 10.0, y, 0.3);
+        ball.borrow_mut().velocity = Vector2d::new(real(0.0), real(vy));
+        (scene, ball)
+    }
+
+    #[test]
+    fn one_way_platforms_let_bodies_up() {
+        let (mut scene, ball) = platform_and_ball(12.0, -8.0);
+        let mut highest = f64::MAX;
+        for _ in 0..180 {
+            scene.step();
+            highest = highest.min(ball.borrow().position.y.to_f64());
+        }
+        // Up through the platform, then down onto it
+        assert!(highest < 9.0);
+        let ball = ball.borrow();
+        assert!((ball.position.y.to_f64() - 9.45).abs() < 0.05);
+        assert!(ball.velocity.len() < real(0.1));
+    }
+
+    #[test]
+    fn one_way_platforms_stop_bodies_from_above() {
+        for &vy in &[0.0, 5.0] {
+            let (mut scene, ball) = platform_and_ball(8.0, vy);
+            for _ in 0..120 {
+                scene.step();
+                // Never more than a step's worth into the platform
+                assert!(ball.borrow().position.y.to_f64() < 9.6);
+            }
+            let ball = ball.borrow();
+            assert!((ball.position.y.to_f64() - 9.45).abs() < 0.05);
+            assert!(ball.velocity.len() < real(0.1));
+        }
+    }
+
+    #[test]
+    fn bodies_passing_one_way_platforms_keep_passing() {
+        // Jumps into the platform and stops halfway, above its middle, then falls back out
+        let (mut scene, ball) = platform_and_ball(10.8, -4.2);
+        let mut highest = f64::MAX;
+        for _ in 0..90 {
+            scene.step();
+            highest = highest.min(ball.borrow().position.y.to_f64());
+        }
+        assert!(highest > 9.75 && highest < 10.0, "{}", highest);
+        let ball = ball.borrow();
+        assert!(ball.position.y.to_f64() > 11.0);
+        assert!(ball.velocity.y > real(0.0));
+    }
+
+    // The fastest a bouncy ball dropped on the floor at `speed` goes back up
+    fn rebound_speed(speed: f64) -> f64 {
+        let (mut scene, _) = floor_scene();
+        let ball = add_ball(&mut scene, 10.0, 15.98, 0.5);
+        ball.borrow_mut().material = Material::rubber();
+        ball.borrow_mut().velocity = Vector2d::new(real(0.0), real(speed));
+        (0..60)
+            .map(|_| {
+                scene.step();
+                -ball.borrow().velocity.y.to_f64()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn slow_impacts_do_not_bounce() {
+        // The threshold is 1 by default, and gravity adds a little before the impact
+        assert!(rebound_speed(0.3) < 0.05);
+        assert!(rebound_speed(0.5) < 0.05);
+        let rebound = rebound_speed(3.0);
+        assert!(rebound > 1.5, "{}", rebound);
+    }
+
+    // The deepest overlap in a stack of boxes after it settled
+    fn stack_overlap(position_iterations: u32) -> f64 {
+        let (mut scene, _) = floor_scene();
+        scene.set_position_correction(position_iterations, real(0.005), real(0.4));
+        for i in 0..5 {
+            add_box(&mut scene, 10.0, 15.9 - 1.0 * i as f64);
+        }
+        for _ in 0..240 {
+            scene.step();
+        }
+        assert_eq!(scene.bodies().len(), 6);
+        scene
+            .contacts()
+            .iter()
+            .map(|contact| contact.penetration().to_f64())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn position_correction_reduces_overlap() {
+        let corrected = stack_overlap(4);
+        let uncorrected = stack_overlap(0);
+        assert!(corrected < 0.02, "{}", corrected);
+        assert!(
+            corrected < uncorrected / 2.0,
+            "{} {}",
+            corrected,
+            uncorrected
+        );
+    }
+
+    #[test]
+    fn same_seed_replays_bit_identically() {
+        let mut a = pile(7);
+        let mut b = pile(7);
+        assert_eq!(state_bits(&a), state_bits(&b));
+        let history = run(&mut a, 300);
+        assert_eq!(history, run(&mut b, 300));
+        // Something happened, the bodies did not just stay where they were
+        assert_ne!(history[0], history[299]);
+        assert!(!a.contacts().is_empty());
+    }
+
+    #[test]
+    fn restore_rewinds_exactly() {
+        let mut scene = pile(11);
+        run(&mut scene, 60);
+        let snapshot = scene.snapshot();
+        let before = state_bits(&scene);
+        let history = run(&mut scene, 120);
+
+        // Bodies added after the snapshot are removed again
+        scene.add_circle(10.0, 2.0);
+        scene.restore(&snapshot).unwrap();
+        assert_eq!(state_bits(&scene), before);
+        assert_eq!(run(&mut scene, 120), history);
+    }
+
+    #[test]
+    fn snapshots_of_other_scenes_are_refused() {
+        let mut scene = pile(11);
+        let mut small = Scene::with_seed(11);
+        small.add_circle(10.0, 2.0);
+        let (count, before) = (scene.bodies().len(), state_bits(&scene));
+        for snapshot in &[small.snapshot(), pile(11).snapshot()] {
+            assert_eq!(scene.restore(snapshot), Err(RestoreError::UnknownBody(0)));
+            assert_eq!(scene.bodies().len(), count);
+            assert_eq!(state_bits(&scene), before);
+        }
+        // Nor can it bring back bodies that are gone
+        let snapshot = pile(11).snapshot();
+        assert!(small.restore(&snapshot).is_err());
+    }
+
+    #[test]
+    fn rendered_frames_decode_as_png() {
+        let scene = Scene::with_seed(3);
+        // The canvas is 20 world units wide, so 8 pixels per unit
+        let raster = scene.render_raster(160, 160);
+        let data = raster.encode_png().unwrap();
+        let mut reader = png::Decoder::new(&data[..]).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (160, 160));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&pixels[..info.buffer_size()], raster.pixels());
+
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 160 + x) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+        let color = |body: &dyn RigidBody| {
+            let color = body.object().borrow().color.clone();
+            [color.r, color.g, color.b, 255]
+        };
+        // The top of the floor, from (1, 16.5) to (19, 16.5)
+        let floor = &*scene.bodies()[1];
+        for &x in &[8, 80, 151] {
+            assert_eq!(pixel(x, 132), color(floor), "{}", x);
+        }
+        // The radius of the circle at (10, 10) drawn to show its orientation
+        let circle = &*scene.bodies()[0];
+        assert_eq!(pixel(80, 84), color(circle));
+        // Nothing drawn elsewhere, outlines are not filled
+        assert_eq!(pixel(40, 40), [0; 4]);
+        assert_eq!(pixel(60, 136), [0; 4]);
+    }
+
+    #[test]
+    fn png_sequences_are_numbered_by_saved_frame() {
+        let directory = std::env::temp_dir().join(format!("impulse-frames-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let mut scene = Scene::with_seed(3);
+        let mut steps = Vec::new();
+        scene
+            .record_png_sequence(7, 3, 20, 20, &directory, |scene, step| {
+                steps.push(step);
+                scene.step();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(steps, (1..=7).collect::<Vec<_>>());
+        let mut names: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["frame_00000.png", "frame_00001.png"]);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(scene
+            .record_png_sequence(1, 0, 20, 20, &directory, |_, _| Ok(()))
+            .is_err());
+    }
+
+    // How far a box thrown along x slides before it stops
+    fn slide_distance(anisotropic_friction: (f64, f64), orient: f64) -> f64 {
+        let (mut scene, _) = floor_scene();
+        let block = add_box(&mut scene, 4.0, 16.0);
+        {
+            let mut object = block.borrow_mut();
+            object.anisotropic_friction =
+                Vector2d::new(anisotropic_friction.0, anisotropic_friction.1).to_real();
+            object.orient = real(orient);
+            object.velocity = Vector2d::new(real(3.0), real(0.0));
+        }
+        for _ in 0..600 {
+            scene.step();
+            let object = block.borrow();
+            if object.velocity.x <= real(0.0) {
+                return object.position.x.to_f64() - 4.0;
+            }
+        }
+        panic!("the box is still sliding");
+    }
+
+    #[test]
+    fn boxes_slide_further_along_their_slippery_axis() {
+        let plain = slide_distance((1.0, 1.0), 0.0);
+        let along = slide_distance((0.2, 1.0), 0.0);
+        // A quarter turn puts the rough local y axis along the motion
+        let across = slide_distance((0.2, 1.0), std::f64::consts::FRAC_PI_2);
+        assert!(along > 4.0 * plain, "{} {}", along, plain);
+        assert!((across - plain).abs() < 0.1 * plain, "{} {}", across, plain);
     }
 }
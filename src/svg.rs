@@ -0,0 +1,1137 @@
+use crate::body::*;
+use crate::geometry::*;
+use crate::level::*;
+use crate::math::*;
+use crate::render::*;
+use roxmltree::{Document, Node};
+use std::fs;
+use std::path::Path;
+
+pub struct SvgOptions {
+    // SVG user units (pixels) per world unit
+    pub pixels_per_unit: f64,
+    // Largest distance between a curve and the segments replacing it, in pixels
+    pub tolerance: f64,
+    // Body type of elements that are not marked with a `static` or `dynamic` class, which can
+    // also be set on a group or layer
+    pub dynamic: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            pixels_per_unit: 40.0,
+            tolerance: 0.25,
+            dynamic: false,
+        }
+    }
+}
+
+// Bodies read from the shapes of an SVG drawing, with everything that could not be imported
+// described in `warnings`.
+// Closed shapes become circles, polygons or compounds for concave outlines, and open ones become
+// static chains, which makes open paths the way to draw terrain.
+pub struct SvgImport {
+    pub level: Level,
+    pub warnings: Vec<String>,
+}
+
+// The affine transform [a c e; b d f]
+#[derive(Clone, Copy)]
+struct Transform([f64; 6]);
+
+impl Transform {
+    fn identity() -> Self {
+        Transform([1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+    }
+
+    // `other` is applied first
+    fn then(self, other: Transform) -> Self {
+        let [a1, b1, c1, d1, e1, f1] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Transform([
+            a1 * a2 + c1 * b2,
+            b1 * a2 + d1 * b2,
+            a1 * c2 + c1 * d2,
+            b1 * c2 + d1 * d2,
+            a1 * e2 + c1 * f2 + e1,
+            b1 * e2 + d1 * f2 + f1,
+        ])
+    }
+
+    fn apply(&self, p: Vector2d<f64>) -> Vector2d<f64> {
+        let [a, b, c, d, e, f] = self.0;
+        Vector2d::new(a * p.x + c * p.y + e, b * p.x + d * p.y + f)
+    }
+
+    // The scale if the transform keeps circles round
+    fn uniform_scale(&self) -> Option<f64> {
+        let [a, b, c, d, _, _] = self.0;
+        let k_tolerance = 1e-6;
+        let scale = (a * d - b * c).abs().sqrt();
+        if (a - d).abs() <= k_tolerance * scale && (b + c).abs() <= k_tolerance * scale {
+            Some(scale)
+        } else {
+            None
+        }
+    }
+}
+
+fn cos_sin(angle: f64) -> (f64, f64) {
+    let v = Vector2d::new(1.0, 0.0).rotate(angle);
+    (v.x, v.y)
+}
+
+// Splits attribute values into numbers, the way SVG allows them to be packed
+struct Scanner<'a> {
+    text: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text: text.as_bytes(),
+            i: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.i < self.text.len()
+            && (self.text[self.i].is_ascii_whitespace() || self.text[self.i] == b',')
+        {
+            self.i += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.i >= self.text.len()
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_separators();
+        self.text.get(self.i).cloned()
+    }
+
+    fn next_is_number(&mut self) -> bool {
+        match self.peek() {
+            Some(c) => c.is_ascii_digit() || c == b'-' || c == b'+' || c == b'.',
+            None => false,
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip_separators();
+        let start = self.i;
+        let text = self.text;
+        let digits = |i: &mut usize| {
+            while *i < text.len() && text[*i].is_ascii_digit() {
+                *i += 1;
+            }
+        };
+        if self.i < text.len() && (text[self.i] == b'-' || text[self.i] == b'+') {
+            self.i += 1;
+        }
+        digits(&mut self.i);
+        if self.i < text.len() && text[self.i] == b'.' {
+            self.i += 1;
+            digits(&mut self.i);
+        }
+        if self.i < text.len() && (text[self.i] == b'e' || text[self.i] == b'E') {
+            let mantissa_end = self.i;
+            self.i += 1;
+            if self.i < text.len() && (text[self.i] == b'-' || text[self.i] == b'+') {
+                self.i += 1;
+            }
+            let exponent_start = self.i;
+            digits(&mut self.i);
+            if self.i == exponent_start {
+                self.i = mantissa_end;
+            }
+        }
+        std::str::from_utf8(&text[start..self.i]).ok()?.parse().ok()
+    }
+
+    // Arc flags may be written without separators, like `a1 1 0 011 1`
+    fn flag(&mut self) -> Option<bool> {
+        match self.peek()? {
+            b'0' => {
+                self.i += 1;
+                Some(false)
+            }
+            b'1' => {
+                self.i += 1;
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+
+    fn point(&mut self) -> Option<Vector2d<f64>> {
+        Some(Vector2d::new(self.number()?, self.number()?))
+    }
+}
+
+fn parse_transform(text: &str) -> Option<Transform> {
+    let mut transform = Transform::identity();
+    for part in text.split(')') {
+        let part = part.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if part.trim().is_empty() {
+            continue;
+        }
+        let mut split = part.splitn(2, '(');
+        let name = split.next()?.trim();
+        let mut scanner = Scanner::new(split.next()?);
+        let mut values = Vec::new();
+        while !scanner.at_end() {
+            values.push(scanner.number()?);
+        }
+        let next = match (name, values.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform([a, b, c, d, e, f]),
+            ("translate", &[x]) => Transform([1.0, 0.0, 0.0, 1.0, x, 0.0]),
+            ("translate", &[x, y]) => Transform([1.0, 0.0, 0.0, 1.0, x, y]),
+            ("scale", &[s]) => Transform([s, 0.0, 0.0, s, 0.0, 0.0]),
+            ("scale", &[x, y]) => Transform([x, 0.0, 0.0, y, 0.0, 0.0]),
+            ("rotate", &[angle]) => {
+                let (c, s) = cos_sin(angle.to_radians());
+                Transform([c, s, -s, c, 0.0, 0.0])
+            }
+            ("rotate", &[angle, x, y]) => {
+                let (c, s) = cos_sin(angle.to_radians());
+                Transform([1.0, 0.0, 0.0, 1.0, x, y])
+                    .then(Transform([c, s, -s, c, 0.0, 0.0]))
+                    .then(Transform([1.0, 0.0, 0.0, 1.0, -x, -y]))
+            }
+            ("skewX", &[angle]) => Transform([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", &[angle]) => Transform([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => return None,
+        };
+        transform = transform.then(next);
+    }
+    Some(transform)
+}
+
+// Wang's formula: segments so that a Bézier curve of `degree` stays within `tolerance`
+fn segment_count(degree: f64, control_points: &[Vector2d<f64>], tolerance: f64) -> usize {
+    let k_max_segments = 100.0;
+    let mut second_difference: f64 = 0.0;
+    for w in control_points.windows(3) {
+        second_difference = second_difference.max((w[0] - w[1] * 2.0 + w[2]).len());
+    }
+    let n = (degree * (degree - 1.0) / 8.0 * second_difference / tolerance)
+        .sqrt()
+        .ceil();
+    n.clamp(1.0, k_max_segments) as usize
+}
+
+// Subpaths of a path in user units, each with whether it is closed
+struct PathBuilder {
+    subpaths: Vec<(Vec<Vector2d<f64>>, bool)>,
+    current: Vec<Vector2d<f64>>,
+    tolerance: f64,
+}
+
+impl PathBuilder {
+    fn position(&self) -> Vector2d<f64> {
+        *self.current.last().unwrap_or(&Vector2d::zero())
+    }
+
+    fn finish(&mut self, closed: bool) {
+        if self.current.len() >= 2 {
+            let points = std::mem::take(&mut self.current);
+            self.subpaths.push((points, closed));
+        } else {
+            self.current.clear();
+        }
+    }
+
+    fn move_to(&mut self, p: Vector2d<f64>) {
+        self.finish(false);
+        self.current.push(p);
+    }
+
+    fn line_to(&mut self, p: Vector2d<f64>) {
+        if self.current.is_empty() {
+            self.current.push(Vector2d::zero());
+        }
+        self.current.push(p);
+    }
+
+    fn quadratic_to(&mut self, c: Vector2d<f64>, p: Vector2d<f64>) {
+        let p0 = self.position();
+        let n = segment_count(2.0, &[p0, c, p], self.tolerance);
+        for i in 1..=n {
+            let t = i as f64 / n as f64;
+            let u = 1.0 - t;
+            self.line_to(p0 * (u * u) + c * (2.0 * u * t) + p * (t * t));
+        }
+    }
+
+    fn cubic_to(&mut self, c1: Vector2d<f64>, c2: Vector2d<f64>, p: Vector2d<f64>) {
+        let p0 = self.position();
+        let n = segment_count(3.0, &[p0, c1, c2, p], self.tolerance);
+        for i in 1..=n {
+            let t = i as f64 / n as f64;
+            let u = 1.0 - t;
+            self.line_to(
+                p0 * (u * u * u)
+                    + c1 * (3.0 * u * u * t)
+                    + c2 * (3.0 * u * t * t)
+                    + p * (t * t * t),
+            );
+        }
+    }
+
+    // Elliptical arc from the current point, converted to its center as in the SVG spec
+    fn arc_to(
+        &mut self,
+        radii: Vector2d<f64>,
+        rotation: f64,
+        large: bool,
+        sweep: bool,
+        p: Vector2d<f64>,
+    ) {
+        let p0 = self.position();
+        let (mut rx, mut ry) = (radii.x.abs(), radii.y.abs());
+        if rx == 0.0 || ry == 0.0 || p0 == p {
+            self.line_to(p);
+            return;
+        }
+        let (c, s) = cos_sin(rotation.to_radians());
+        let half = (p0 - p) / 2.0;
+        let x1 = c * half.x + s * half.y;
+        let y1 = -s * half.x + c * half.y;
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let mut k = (numerator / denominator).max(0.0).sqrt();
+        if large == sweep {
+            k = -k;
+        }
+        let cx1 = k * rx * y1 / ry;
+        let cy1 = -k * ry * x1 / rx;
+        let middle = (p0 + p) / 2.0;
+        let center = Vector2d::new(c * cx1 - s * cy1 + middle.x, s * cx1 + c * cy1 + middle.y);
+
+        let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+        let end = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+        let mut delta = end - start;
+        if sweep && delta < 0.0 {
+            delta += 2.0 * PI;
+        } else if !sweep && delta > 0.0 {
+            delta -= 2.0 * PI;
+        }
+
+        let radius = rx.max(ry);
+        let step = if self.tolerance < radius {
+            2.0 * (1.0 - self.tolerance / radius).acos()
+        } else {
+            PI / 2.0
+        };
+        let n = (delta.abs() / step).ceil().clamp(1.0, 100.0) as usize;
+        for i in 1..n {
+            let (ca, sa) = cos_sin(start + delta * i as f64 / n as f64);
+            let (x, y) = (rx * ca, ry * sa);
+            self.line_to(Vector2d::new(c * x - s * y, s * x + c * y) + center);
+        }
+        self.line_to(p);
+    }
+}
+
+fn parse_path(d: &str, tolerance: f64) -> Option<Vec<(Vec<Vector2d<f64>>, bool)>> {
+    let mut scanner = Scanner::new(d);
+    let mut path = PathBuilder {
+        subpaths: Vec::new(),
+        current: Vec::new(),
+        tolerance,
+    };
+    let mut start = Vector2d::zero();
+    // Reflected by the smooth curve commands
+    let mut last_control: Option<Vector2d<f64>> = None;
+    let mut command = b'M';
+    while !scanner.at_end() {
+        if !scanner.next_is_number() {
+            command = scanner.peek()?;
+            scanner.i += 1;
+            if command == b'Z' || command == b'z' {
+                path.finish(true);
+                path.current.push(start);
+                last_control = None;
+                continue;
+            }
+        }
+        let relative = command.is_ascii_lowercase();
+        let origin = if relative {
+            path.position()
+        } else {
+            Vector2d::zero()
+        };
+        let position = path.position();
+        let mut control = None;
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                let p = scanner.point()? + origin;
+                path.move_to(p);
+                start = p;
+                // Numbers after a move are lines
+                command = if relative { b'l' } else { b'L' };
+            }
+            b'L' => path.line_to(scanner.point()? + origin),
+            b'H' => {
+                let x = scanner.number()? + origin.x;
+                path.line_to(Vector2d::new(x, position.y));
+            }
+            b'V' => {
+                let y = scanner.number()? + origin.y;
+                path.line_to(Vector2d::new(position.x, y));
+            }
+            b'C' => {
+                let c1 = scanner.point()? + origin;
+                let c2 = scanner.point()? + origin;
+                let p = scanner.point()? + origin;
+                path.cubic_to(c1, c2, p);
+                control = Some(c2);
+            }
+            b'S' => {
+                let c1 = last_control.map_or(position, |c| position * 2.0 - c);
+                let c2 = scanner.point()? + origin;
+                let p = scanner.point()? + origin;
+                path.cubic_to(c1, c2, p);
+                control = Some(c2);
+            }
+            b'Q' => {
+                let c = scanner.point()? + origin;
+                let p = scanner.point()? + origin;
+                path.quadratic_to(c, p);
+                control = Some(c);
+            }
+            b'T' => {
+                let c = last_control.map_or(position, |c| position * 2.0 - c);
+                let p = scanner.point()? + origin;
+                path.quadratic_to(c, p);
+                control = Some(c);
+            }
+            b'A' => {
+                let radii = scanner.point()?;
+                let rotation = scanner.number()?;
+                let large = scanner.flag()?;
+                let sweep = scanner.flag()?;
+                let p = scanner.point()? + origin;
+                path.arc_to(radii, rotation, large, sweep, p);
+            }
+            _ => return None,
+        }
+        // Only a curve of the same kind can be smooth, which the spec leaves to the control
+        // point being reflected. Mixing quadratic and cubic ones is rare enough to ignore.
+        last_control = control;
+    }
+    path.finish(false);
+    Some(path.subpaths)
+}
+
+// A length attribute in user units, `px` and missing units are the same
+fn length(node: Node, name: &str) -> Option<f64> {
+    let value = node.attribute(name)?.trim().trim_end_matches("px");
+    let mut scanner = Scanner::new(value);
+    let number = scanner.number()?;
+    if scanner.at_end() {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+fn points(text: &str) -> Option<Vec<Vector2d<f64>>> {
+    let mut scanner = Scanner::new(text);
+    let mut points = Vec::new();
+    while !scanner.at_end() {
+        points.push(scanner.point()?);
+    }
+    Some(points)
+}
+
+// The fill color, from the attribute or the style, when it is a hex color
+fn fill(node: Node) -> Option<Color> {
+    let style_fill = node.attribute("style").and_then(|style| {
+        style
+            .split(';')
+            .filter_map(|declaration| {
+                let mut split = declaration.splitn(2, ':');
+                Some((split.next()?.trim(), split.next()?.trim()))
+            })
+            .find(|&(name, _)| name == "fill")
+            .map(|(_, value)| value)
+    });
+    parse_hex_color(style_fill.or_else(|| node.attribute("fill"))?)
+}
+
+fn describe(node: Node) -> String {
+    match node.attribute("id") {
+        Some(id) => format!("<{} id=\"{}\">", node.tag_name().name(), id),
+        None => format!(
+            "<{}> at byte {}",
+            node.tag_name().name(),
+            node.range().start
+        ),
+    }
+}
+
+fn has_class(node: Node, class: &str) -> bool {
+    node.attribute("class")
+        .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+}
+
+// Drop repeated points, the closing point of closed outlines and points in the middle of a
+// straight line, which the flattening leaves behind
+fn simplify(points: &[Vector2d<f64>], closed: bool) -> Vec<Vector2d<f64>> {
+    let k_epsilon = 1e-9;
+    let mut result: Vec<Vector2d<f64>> = Vec::new();
+    for &p in points {
+        if result.last().is_none_or(|&q| (p - q).len() > k_epsilon) {
+            result.push(p);
+        }
+    }
+    if closed {
+        while result.len() > 1 && (result[0] - result[result.len() - 1]).len() <= k_epsilon {
+            result.pop();
+        }
+    }
+    let mut i = 0;
+    while result.len() > 2 && i < result.len() {
+        let n = result.len();
+        if !closed && (i == 0 || i == n - 1) {
+            i += 1;
+            continue;
+        }
+        let previous = result[(i + n - 1) % n];
+        let next = result[(i + 1) % n];
+        let (a, b) = (result[i] - previous, next - result[i]);
+        if a.cross_product(b).abs() <= k_epsilon * a.len() * b.len() && a * b > 0.0 {
+            result.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    result
+}
+
+impl SvgImport {
+    pub fn from_svg(text: &str, options: &SvgOptions) -> Result<Self, LevelError> {
+        let document = Document::parse(text)?;
+        let mut import = SvgImport {
+            level: Level::default(),
+            warnings: Vec::new(),
+        };
+        import.visit(
+            document.root_element(),
+            Transform::identity(),
+            options.dynamic,
+            options,
+        );
+        Ok(import)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, options: &SvgOptions) -> Result<Self, LevelError> {
+        Self::from_svg(&fs::read_to_string(path)?, options)
+    }
+
+    fn visit(&mut self, node: Node, parent: Transform, dynamic: bool, options: &SvgOptions) {
+        let transform = match node.attribute("transform") {
+            Some(text) => match parse_transform(text) {
+                Some(transform) => parent.then(transform),
+                None => {
+                    self.warn(node, "cannot read the transform, skipped");
+                    return;
+                }
+            },
+            None => parent,
+        };
+        let dynamic = if has_class(node, "dynamic") {
+            true
+        } else if has_class(node, "static") {
+            false
+        } else {
+            dynamic
+        };
+        match node.tag_name().name() {
+            "svg" | "g" | "a" | "switch" => {
+                for child in node.children().filter(|n| n.is_element()) {
+                    self.visit(child, transform, dynamic, options);
+                }
+            }
+            // Not drawn by themselves
+            "defs" | "symbol" | "clipPath" | "mask" | "marker" | "pattern" | "metadata"
+            | "title" | "desc" | "style" | "namedview" => {}
+            "rect" => self.import_rect(node, transform, dynamic, options),
+            "circle" | "ellipse" => self.import_ellipse(node, transform, dynamic, options),
+            "polygon" | "polyline" => {
+                let closed = node.tag_name().name() == "polygon";
+                match node.attribute("points").and_then(points) {
+                    Some(points) => {
+                        self.add_outline(node, &points, closed, transform, dynamic, options)
+                    }
+                    None => self.warn(node, "cannot read the points, skipped"),
+                }
+            }
+            "line" => {
+                let v1 = Vector2d::new(
+                    length(node, "x1").unwrap_or(0.0),
+                    length(node, "y1").unwrap_or(0.0),
+                );
+                let v2 = Vector2d::new(
+                    length(node, "x2").unwrap_or(0.0),
+                    length(node, "y2").unwrap_or(0.0),
+                );
+                self.add_outline(node, &[v1, v2], false, transform, dynamic, options);
+            }
+            "path" => {
+                let d = node.attribute("d").unwrap_or("");
+                // The tolerance is in pixels, shapes scaled up need finer curves
+                let scale = transform.uniform_scale().unwrap_or(1.0).max(1e-9);
+                match parse_path(d, options.tolerance / scale) {
+                    Some(subpaths) => {
+                        for (points, closed) in subpaths {
+                            self.add_outline(node, &points, closed, transform, dynamic, options);
+                        }
+                    }
+                    None => self.warn(node, "cannot read the path data, skipped"),
+                }
+            }
+            "text" | "image" | "use" => self.warn(node, "element is not supported, skipped"),
+            _ => {}
+        }
+    }
+
+    fn warn(&mut self, node: Node, message: &str) {
+        self.warnings
+            .push(format!("{}: {}", describe(node), message));
+    }
+
+    fn import_rect(
+        &mut self,
+        node: Node,
+        transform: Transform,
+        dynamic: bool,
+        options: &SvgOptions,
+    ) {
+        let x = length(node, "x").unwrap_or(0.0);
+        let y = length(node, "y").unwrap_or(0.0);
+        let (width, height) = match (length(node, "width"), length(node, "height")) {
+            (Some(w), Some(h)) if w > 0.0 && h > 0.0 => (w, h),
+            _ => {
+                self.warn(node, "rectangle without a positive size, skipped");
+                return;
+            }
+        };
+        if length(node, "rx").unwrap_or(0.0) > 0.0 || length(node, "ry").unwrap_or(0.0) > 0.0 {
+            self.warn(node, "rounded corners are not supported, imported as sharp");
+        }
+        let corners = [
+            Vector2d::new(x, y),
+            Vector2d::new(x + width, y),
+            Vector2d::new(x + width, y + height),
+            Vector2d::new(x, y + height),
+        ];
+        self.add_outline(node, &corners, true, transform, dynamic, options);
+    }
+
+    fn import_ellipse(
+        &mut self,
+        node: Node,
+        transform: Transform,
+        dynamic: bool,
+        options: &SvgOptions,
+    ) {
+        let center = Vector2d::new(
+            length(node, "cx").unwrap_or(0.0),
+            length(node, "cy").unwrap_or(0.0),
+        );
+        let (rx, ry) = if node.tag_name().name() == "circle" {
+            let r = length(node, "r").unwrap_or(0.0);
+            (r, r)
+        } else {
+            (
+                length(node, "rx").unwrap_or(0.0),
+                length(node, "ry").unwrap_or(0.0),
+            )
+        };
+        if rx <= 0.0 || ry <= 0.0 {
+            self.warn(node, "circle without a positive radius, skipped");
+            return;
+        }
+        match transform.uniform_scale() {
+            Some(scale) if rx == ry => {
+                let position = transform.apply(center) / options.pixels_per_unit;
+                self.add_body(
+                    node,
+                    Shape::Circle {
+                        radius: real(rx * scale / options.pixels_per_unit),
+                    },
+                    position,
+                    dynamic,
+                );
+            }
+            // Squashed circles are not circles anymore
+            _ => {
+                let radius = rx.max(ry) * transform.uniform_scale().unwrap_or(1.0);
+                let step = if options.tolerance < radius {
+                    2.0 * (1.0 - options.tolerance / radius).acos()
+                } else {
+                    PI / 2.0
+                };
+                let n = (2.0 * PI / step).ceil().clamp(8.0, 100.0) as usize;
+                let points: Vec<_> = (0..n)
+                    .map(|i| {
+                        let (c, s) = cos_sin(2.0 * PI * i as f64 / n as f64);
+                        center + Vector2d::new(rx * c, ry * s)
+                    })
+                    .collect();
+                self.add_outline(node, &points, true, transform, dynamic, options);
+            }
+        }
+    }
+
+    fn add_outline(
+        &mut self,
+        node: Node,
+        points: &[Vector2d<f64>],
+        closed: bool,
+        transform: Transform,
+        dynamic: bool,
+        options: &SvgOptions,
+    ) {
+        let points: Vec<_> = points
+            .iter()
+            .map(|&p| transform.apply(p) / options.pixels_per_unit)
+            .collect();
+        let points = simplify(&points, closed);
+        let mut center = Vector2d::zero();
+        for &p in &points {
+            center += p;
+        }
+        center /= points.len().max(1) as f64;
+        let vertices: Vec<_> = points.iter().map(|&p| (p - center).to_real()).collect();
+
+        if !closed {
+            if vertices.len() < 2 {
+                self.warn(node, "line without length, skipped");
+                return;
+            }
+            if dynamic {
+                self.warn(node, "open shapes become chains, which are always static");
+            }
+            let shape = Shape::Chain {
+                vertices,
+                is_loop: false,
+            };
+            self.add_body(node, shape, center, false);
+            return;
+        }
+
+        let mut convex = vertices.clone();
+        if signed_area(&convex) < real(0.0) {
+            convex.reverse();
+        }
+        let shape = match validate_convex(&convex) {
+            Ok(()) => Shape::Polygon { vertices: convex },
+            Err(PolygonError::NonConvex) => match decompose(&vertices) {
+                Ok(_) => Shape::Compound {
+                    vertices,
+                    pieces: Vec::new(),
+                },
+                Err(e) => {
+                    self.warn(node, &format!("{}, skipped", e));
+                    return;
+                }
+            },
+            Err(e) => {
+                self.warn(node, &format!("{}, skipped", e));
+                return;
+            }
+        };
+        self.add_body(node, shape, center, dynamic);
+    }
+
+    fn add_body(&mut self, node: Node, shape: Shape, position: Vector2d<f64>, dynamic: bool) {
+        self.level.bodies.push(BodyDef {
+            shape,
+            position: position.to_real(),
+            is_static: !dynamic,
+            color: fill(node),
+            ..BodyDef::default()
+        });
+    }
+}
+
+// Draws into a standalone SVG document instead of a canvas
+pub struct SvgContext {
+    elements: Vec<String>,
+    path: String,
+    fill: String,
+    stroke: String,
+    font: String,
+}
+
+impl Default for SvgContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Text is escaped for both element content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl SvgContext {
+    pub fn new() -> Self {
+        // The canvas defaults
+        Self {
+            elements: Vec::new(),
+            path: String::new(),
+            fill: "#000000".to_string(),
+            stroke: "#000000".to_string(),
+            font: "10px sans-serif".to_string(),
+        }
+    }
+
+    // The document with everything drawn since the last clear
+    pub fn finish(&self, width: f64, height: f64) -> String {
+        let mut document = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+            width, height, width, height
+        );
+        for element in &self.elements {
+            document.push_str("  ");
+            document.push_str(element);
+            document.push('\n');
+        }
+        document.push_str("</svg>\n");
+        document
+    }
+}
+
+impl Context2d for SvgContext {
+    // Cleared pixels are transparent, like an empty document
+    fn clear_rect(&mut self, _x: f64, _y: f64, _width: f64, _height: f64) {
+        self.elements.clear();
+    }
+    fn set_font(&mut self, font: &str) {
+        self.font = font.to_string();
+    }
+    fn set_fill_style_color(&mut self, color: &str) {
+        self.fill = color.to_string();
+    }
+    fn set_stroke_style_color(&mut self, color: &str) {
+        self.stroke = color.to_string();
+    }
+    fn fill_text(&mut self, text: &str, x: f64, y: f64, _max_width: Option<f64>) {
+        self.elements.push(format!(
+            "<text x=\"{:.2}\" y=\"{:.2}\" style=\"font: {}\" fill=\"{}\">{}</text>",
+            x,
+            y,
+            escape(&self.font),
+            escape(&self.fill),
+            escape(text)
+        ));
+    }
+    fn begin_path(&mut self) {
+        self.path.clear();
+    }
+    fn close_path(&mut self) {
+        self.path.push_str(" Z");
+    }
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.path.push_str(&format!(" M {:.2} {:.2}", x, y));
+    }
+    fn line_to(&mut self, x: f64, y: f64) {
+        self.path.push_str(&format!(" L {:.2} {:.2}", x, y));
+    }
+    fn stroke(&mut self) {
+        self.elements.push(format!(
+            "<path d=\"{}\" fill=\"none\" stroke=\"{}\"/>",
+            self.path.trim_start(),
+            escape(&self.stroke)
+        ));
+    }
+    fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.elements.push(format!(
+            "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>",
+            x,
+            y,
+            width,
+            height,
+            escape(&self.fill)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    fn import_with(elements: &str, options: &SvgOptions) -> SvgImport {
+        let text = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">{}</svg>"#,
+            elements
+        );
+        SvgImport::from_svg(&text, options).unwrap()
+    }
+
+    // One pixel per world unit, so that points keep their coordinates
+    fn import(elements: &str) -> SvgImport {
+        let options = SvgOptions {
+            pixels_per_unit: 1.0,
+            ..SvgOptions::default()
+        };
+        import_with(elements, &options)
+    }
+
+    fn path(d: &str) -> SvgImport {
+        import(&format!(r#"<path d="{}"/>"#, d))
+    }
+
+    fn world_points(body: &BodyDef) -> Vec<Vector2d<f64>> {
+        let vertices = match body.shape {
+            Shape::Polygon { ref vertices }
+            | Shape::Compound { ref vertices, .. }
+            | Shape::Chain { ref vertices, .. } => vertices,
+            _ => panic!("shape without vertices"),
+        };
+        vertices
+            .iter()
+            .map(|&v| (v + body.position).to_f64())
+            .collect()
+    }
+
+    fn has_point(points: &[Vector2d<f64>], x: f64, y: f64) -> bool {
+        points
+            .iter()
+            .any(|p| (*p - Vector2d::new(x, y)).len() < 1e-6)
+    }
+
+    fn y_range(points: &[Vector2d<f64>]) -> (f64, f64) {
+        points.iter().fold((f64::MAX, f64::MIN), |(low, high), p| {
+            (low.min(p.y), high.max(p.y))
+        })
+    }
+
+    #[test]
+    fn arcs_follow_the_circle() {
+        // Radii too small for the end points are scaled up to 10
+        for d in &[
+            "M 0 0 A 10 10 0 0 1 20 0 Z",
+            "M 0 0 a 10 10 0 0 1 20 0 z",
+            "M 0 0 A 1 1 0 0 1 20 0 Z",
+        ] {
+            let import = path(d);
+            assert!(import.warnings.is_empty(), "{}: {:?}", d, import.warnings);
+            assert_eq!(import.level.bodies.len(), 1);
+            let points = world_points(&import.level.bodies[0]);
+            assert!(points.len() > 8);
+            for p in &points {
+                assert!((*p - Vector2d::new(10.0, 0.0)).len() <= 10.0 + 1e-6);
+            }
+            // The sweep flag goes through negative angles, which is up on the canvas
+            let (low, high) = y_range(&points);
+            assert!((low + 10.0).abs() < 0.25, "{}: {}", d, low);
+            assert!(high.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn shorthand_commands() {
+        for d in &["M 0 0 H 10 V 10 H 0 Z", "m 0 0 h 10 v 10 h -10 z"] {
+            let points = world_points(&path(d).level.bodies[0]);
+            assert_eq!(points.len(), 4);
+            for &(x, y) in &[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)] {
+                assert!(has_point(&points, x, y), "{}: ({}, {})", d, x, y);
+            }
+        }
+        // The smooth curves reflect the last control point, so the second half bulges the
+        // other way as much as the first one
+        let points = world_points(&path("M 0 0 C 0 -10 10 -10 10 0 S 20 10 20 0").level.bodies[0]);
+        let (low, high) = y_range(&points);
+        assert!((low + 7.5).abs() < 0.25 && (high - 7.5).abs() < 0.25);
+        assert!(has_point(&points, 20.0, 0.0));
+        let points = world_points(&path("M 0 0 Q 5 -10 10 0 T 20 0").level.bodies[0]);
+        let (low, high) = y_range(&points);
+        assert!((low + 5.0).abs() < 0.25 && (high - 5.0).abs() < 0.25);
+    }
+
+    #[test]
+    fn nested_transforms_apply_inner_first() {
+        let import = import(
+            r#"<g transform="translate(100 0)">
+                <g transform="scale(2)"><rect x="1" y="1" width="2" height="3"/></g>
+            </g>"#,
+        );
+        let points = world_points(&import.level.bodies[0]);
+        assert_eq!(points.len(), 4);
+        for &(x, y) in &[(102.0, 2.0), (106.0, 2.0), (106.0, 8.0), (102.0, 8.0)] {
+            assert!(has_point(&points, x, y), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn concave_closed_path_becomes_a_compound() {
+        let import = path("M 0 0 H 20 V 10 H 10 V 20 H 0 Z");
+        assert!(import.warnings.is_empty());
+        let body = &import.level.bodies[0];
+        match body.shape {
+            Shape::Compound { ref pieces, .. } => assert!(pieces.is_empty()),
+            _ => panic!("not a compound"),
+        }
+        assert_eq!(world_points(body).len(), 6);
+        assert!(body.is_static);
+    }
+
+    #[test]
+    fn open_path_becomes_a_static_chain() {
+        let d = r#"<path d="M 0 0 L 10 5 L 20 0"/>"#;
+        let import = import(d);
+        assert!(import.warnings.is_empty());
+        let body = &import.level.bodies[0];
+        match body.shape {
+            Shape::Chain { is_loop, .. } => assert!(!is_loop),
+            _ => panic!("not a chain"),
+        }
+        let points = world_points(body);
+        assert_eq!(points.len(), 3);
+        assert!(has_point(&points, 10.0, 5.0));
+        assert!(body.is_static);
+
+        let options = SvgOptions {
+            dynamic: true,
+            ..SvgOptions::default()
+        };
+        let import = import_with(d, &options);
+        assert_eq!(import.warnings.len(), 1);
+        assert!(import.level.bodies[0].is_static);
+    }
+
+    #[test]
+    fn malformed_input_is_a_warning() {
+        for element in &[
+            r#"<path d="M 0 0 L 10"/>"#,
+            r#"<path d="M 0 0 X 5 5"/>"#,
+            r#"<path d="M 0 0 A 10 10 0 2 1 5 5"/>"#,
+            r#"<polygon points="0 0 10"/>"#,
+            r#"<rect width="-1" height="5"/>"#,
+            r#"<g transform="rotate("><rect width="1" height="1"/></g>"#,
+        ] {
+            let import = import(element);
+            assert_eq!(import.warnings.len(), 1, "{}", element);
+            assert!(import.level.bodies.is_empty(), "{}", element);
+        }
+        assert!(SvgImport::from_svg("<svg>", &SvgOptions::default()).is_err());
+    }
+
+    #[test]
+    fn context_writes_paths_rects_and_text() {
+        let mut context = SvgContext::new();
+        context.set_stroke_style_color("#123456");
+        context.begin_path();
+        context.move_to(1.0, 2.0);
+        context.line_to(3.0, 4.5);
+        context.close_path();
+        context.stroke();
+        context.set_fill_style_color("red");
+        context.fill_rect(0.0, 1.0, 2.0, 3.0);
+        context.fill_text("a < b & \"c\"", 5.0, 6.0, None);
+        let document = context.finish(10.0, 20.0);
+        let svg = Document::parse(&document).unwrap();
+        let root = svg.root_element();
+        assert_eq!(root.attribute("width"), Some("10"));
+        assert_eq!(root.attribute("viewBox"), Some("0 0 10 20"));
+        let elements: Vec<_> = root.children().filter(|n| n.is_element()).collect();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(
+            elements[0].attribute("d"),
+            Some("M 1.00 2.00 L 3.00 4.50 Z")
+        );
+        assert_eq!(elements[0].attribute("stroke"), Some("#123456"));
+        assert_eq!(elements[1].attribute("height"), Some("3.00"));
+        assert_eq!(elements[1].attribute("fill"), Some("red"));
+        assert_eq!(elements[2].text(), Some("a < b & \"c\""));
+
+        // Clearing starts an empty document
+        context.clear_rect(0.0, 0.0, 10.0, 20.0);
+        let document = context.finish(10.0, 20.0);
+        let svg = Document::parse(&document).unwrap();
+        assert_eq!(
+            svg.root_element()
+                .children()
+                .filter(|n| n.is_element())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn exported_scene_has_bodies_and_contacts() {
+        let mut scene = Scene::with_seed(7);
+        scene.add_body(Box::new(Circle::new(real(5.0), real(15.55), real(1.0))));
+        let block = Polygon::new_box(
+            Vector2d::new(14.0, 16.05).to_real(),
+            real(0.5),
+            real(0.5),
+            real(0.0),
+        )
+        .unwrap();
+        scene.add_body(Box::new(block));
+        scene.step();
+        // The canvas is 20 world units wide
+        let pixels_per_unit = 20.0;
+        let document = scene.render_svg(400.0, 400.0);
+        let svg = Document::parse(&document).unwrap();
+        assert_eq!(svg.root_element().attribute("width"), Some("400"));
+        // No help text outside the browser
+        assert!(svg.descendants().all(|n| n.tag_name().name() != "text"));
+
+        let paths: Vec<_> = svg
+            .descendants()
+            .filter(|n| n.tag_name().name() == "path")
+            .collect();
+        assert_eq!(paths.len(), scene.bodies().len());
+        for (path, body) in paths.iter().zip(scene.bodies()) {
+            let object = body.object();
+            let object = object.borrow();
+            let color = format!(
+                "#{:02x}{:02x}{:02x}",
+                object.color.r, object.color.g, object.color.b
+            );
+            assert_eq!(path.attribute("stroke"), Some(color.as_str()));
+            assert!(path.attribute("d").unwrap().starts_with('M'));
+        }
+
+        // A red square on every contact point, bodies mark their centers in black
+        let markers: Vec<_> = svg
+            .descendants()
+            .filter(|n| n.tag_name().name() == "rect" && n.attribute("fill") == Some("red"))
+            .collect();
+        let contacts: Vec<_> = scene
+            .contacts()
+            .iter()
+            .flat_map(|manifold| manifold.contacts.iter().map(|c| c.to_f64()))
+            .collect();
+        assert!(!contacts.is_empty());
+        assert_eq!(markers.len(), contacts.len());
+        for (marker, contact) in markers.iter().zip(&contacts) {
+            let x: f64 = marker.attribute("x").unwrap().parse().unwrap();
+            let y: f64 = marker.attribute("y").unwrap().parse().unwrap();
+            let size: f64 = marker.attribute("width").unwrap().parse().unwrap();
+            assert!((x + size / 2.0 - contact.x * pixels_per_unit).abs() < 0.02);
+            assert!((y + size / 2.0 - contact.y * pixels_per_unit).abs() < 0.02);
+        }
+    }
+}